@@ -3,14 +3,45 @@ from __future__ import absolute_import
 from typing import Union, BinaryIO
 
 # Import the Rust extension module
-from ._rustcsv import CSVReader as _RustCSVReader, CSVWriter, __build__
+from ._rustcsv import (
+    CSVDictWriter,
+    CSVReader as _RustCSVReader,
+    CSVWriter,
+    Dialect,
+    __build__,
+    build_index,
+    index_position,
+    read_arrow,
+    read_numpy,
+    sniff,
+)
+from .dataframe import read_dataframe
+from .dialect import get_dialect, list_dialects, register_dialect
+from .parallel import read_parallel
 
 try:
     from ._version import version
 except ImportError:
     version = "UNKNOWN"
 
-__all__ = ["CSVReader", "CSVWriter", "__build__", "version"]
+__all__ = [
+    "CSVDictWriter",
+    "CSVReader",
+    "CSVWriter",
+    "Dialect",
+    "__build__",
+    "build_index",
+    "get_dialect",
+    "index_position",
+    "list_dialects",
+    "read_arrow",
+    "read_dataframe",
+    "read_numpy",
+    "read_parallel",
+    "register_dialect",
+    "sniff",
+    "version",
+]
 
 CSVReader = _RustCSVReader
 # Can't subclass for better docstrings: https://github.com/PyO3/pyo3/issues/220
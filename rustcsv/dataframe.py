@@ -0,0 +1,170 @@
+from typing import Any, BinaryIO, Dict, List, Optional, Sequence, Union
+
+from ._rustcsv import CSVReader
+
+#: Fields treated as missing values by default, the same as
+#: ``pandas.read_csv``.
+DEFAULT_NA_VALUES = [
+    "",
+    "#N/A",
+    "#N/A N/A",
+    "#NA",
+    "-1.#IND",
+    "-1.#QNAN",
+    "-NaN",
+    "-nan",
+    "1.#IND",
+    "1.#QNAN",
+    "<NA>",
+    "N/A",
+    "NA",
+    "NULL",
+    "NaN",
+    "None",
+    "n/a",
+    "nan",
+    "null",
+]
+
+# dtypes that are converted while parsing, others are applied with
+# DataFrame.astype once the columns have been read.
+_RUST_DTYPES = {
+    int: "int",
+    float: "float",
+    bool: "bool",
+    str: "str",
+    "int": "int",
+    "int64": "int",
+    "float": "float",
+    "float64": "float",
+    "bool": "bool",
+    "str": "str",
+    "object": "str",
+}
+
+Column = Union[int, str]
+
+
+def read_dataframe(
+    path_or_fd: Union[str, BinaryIO],
+    sep: str = ",",
+    header: Optional[int] = 0,
+    usecols: Optional[Sequence[Column]] = None,
+    dtype: Any = None,
+    na_values: Union[str, Sequence[str], None] = None,
+    keep_default_na: bool = True,
+    skiprows: Optional[int] = None,
+    nrows: Optional[int] = None,
+    parse_dates: Optional[Sequence[Column]] = None,
+    **options
+):
+    """
+    Reads a CSV into a :class:`pandas.DataFrame`, supporting a subset of the
+    arguments of :func:`pandas.read_csv`.
+
+    The records are read by :class:`rustcsv.CSVReader` in ``columnar`` mode,
+    ``dtype`` and ``na_values`` are applied while parsing.
+    Any other ``options`` are passed to :class:`rustcsv.CSVReader`.
+    """
+    import pandas
+
+    has_headers = header is not None
+    skip_lines = (skiprows or 0) + (header or 0)
+
+    rust_dtypes = {}  # type: Dict[Column, str]
+    other_dtypes = {}  # type: Dict[Column, Any]
+    if isinstance(dtype, dict):
+        for column, column_dtype in dtype.items():
+            rust_dtype = _get_rust_dtype(column_dtype)
+            if rust_dtype is None:
+                other_dtypes[column] = column_dtype
+            else:
+                rust_dtypes[column] = rust_dtype
+
+    all_na_values = list(DEFAULT_NA_VALUES) if keep_default_na else []
+    if isinstance(na_values, str):
+        all_na_values.append(na_values)
+    elif na_values is not None:
+        all_na_values.extend(na_values)
+
+    reader = CSVReader(
+        path_or_fd,
+        delimiter=sep.encode(),
+        has_headers=has_headers,
+        skip_lines=skip_lines,
+        dtypes=rust_dtypes or None,
+        na_values=all_na_values or None,
+        columnar=True,
+        **options
+    )
+
+    if nrows is None:
+        batches = reader
+    elif nrows > 0:
+        batches = [reader.read_batch(nrows)]
+    else:
+        batches = []
+
+    columns = {}  # type: Dict[Column, List[Any]]
+    for batch in batches:
+        for key, values in batch.items():
+            columns.setdefault(key, []).extend(values)
+
+    if reader.fieldnames is not None:
+        names = list(reader.fieldnames)  # type: List[Column]
+    else:
+        names = sorted(columns)
+
+    if usecols is not None:
+        names = [
+            names[column]
+            if isinstance(column, int) and has_headers
+            else column
+            for column in usecols
+        ]
+
+    df = pandas.DataFrame(
+        {name: columns.get(name, []) for name in names}, columns=names
+    )
+
+    # Like pandas.read_csv, int columns with missing values are float64 with
+    # NaN, rather than objects.
+    for column, rust_dtype in rust_dtypes.items():
+        if isinstance(column, int) and reader.fieldnames is not None:
+            column = reader.fieldnames[column]
+        if rust_dtype == "int" and column in df.columns:
+            if df[column].dtype == object:
+                df[column] = df[column].astype("float64")
+
+    if dtype is not None and not isinstance(dtype, dict):
+        other_dtypes = {name: dtype for name in df.columns}
+    for column, column_dtype in other_dtypes.items():
+        name = _get_name(df, column)
+        df[name] = _astype(df[name], column_dtype)
+
+    for column in parse_dates or []:
+        name = _get_name(df, column)
+        df[name] = pandas.to_datetime(df[name])
+
+    return df
+
+
+def _get_rust_dtype(dtype: Any) -> Optional[str]:
+    try:
+        return _RUST_DTYPES.get(dtype)
+    except TypeError:  # unhashable
+        return None
+
+
+def _astype(series, dtype: Any):
+    # Series.astype would convert missing values too, e.g. None to "None".
+    missing = series.isna()
+    if not missing.any():
+        return series.astype(dtype)
+    return series[~missing].astype(dtype).reindex(series.index)
+
+
+def _get_name(df, column: Column) -> Column:
+    if isinstance(column, int) and column not in df.columns:
+        return df.columns[column]
+    return column
@@ -0,0 +1,51 @@
+import csv
+from typing import List, Type, Union
+
+from ._rustcsv import Dialect
+
+#: Like :class:`csv.excel`, records are terminated by ``b"\r\n"``. This is
+#: the format described by RFC 4180, and also registered as ``"rfc4180"``.
+excel = Dialect(terminator=b"\r\n")
+#: Like :class:`csv.excel_tab`, :data:`excel` with tab-separated fields.
+excel_tab = Dialect(excel, delimiter=b"\t")
+#: Like :class:`csv.unix_dialect`, every field is quoted when writing.
+unix = Dialect(terminator=b"\n", quote_style="always")
+
+_dialects = {
+    "excel": excel,
+    "excel_tab": excel_tab,
+    "unix": unix,
+    "rfc4180": excel,
+}
+
+
+def register_dialect(
+    name: str, dialect: Union[Dialect, str, Type[csv.Dialect]]
+) -> None:
+    """
+    Registers ``dialect`` under ``name``, so that ``name`` can be passed as
+    the ``dialect`` of :class:`rustcsv.CSVReader` and the writers.
+
+    ``dialect`` is converted to a :class:`rustcsv.Dialect` when it's
+    registered.
+    """
+    _dialects[name] = Dialect(dialect)
+
+
+def get_dialect(name: str) -> Dialect:
+    """
+    Returns the dialect registered under ``name``.
+
+    Raises :class:`KeyError` if no dialect is registered under ``name``.
+    """
+    try:
+        return _dialects[name]
+    except KeyError:
+        raise KeyError("Unknown dialect: {!r}".format(name)) from None
+
+
+def list_dialects() -> List[str]:
+    """
+    Returns the names of the registered dialects.
+    """
+    return sorted(_dialects)
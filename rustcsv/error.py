@@ -1,4 +1,4 @@
-from typing import Optional
+from typing import Optional, Union
 
 import attr
 
@@ -6,7 +6,7 @@ import attr
 @attr.s(slots=True, cmp=True, frozen=True)
 class Position:
     byte = attr.ib(type=int)  # type: int
-    line = attr.ib(type=int)  # type: int
+    line = attr.ib(type=Optional[int])  # type: Optional[int]
     record = attr.ib(type=int)  # type: int
 
 
@@ -28,3 +28,15 @@ class UnequalLengthsError(CSVError):
     position = attr.ib(
         None, type=Optional[Position]
     )  # type: Optional[Position]
+
+
+@attr.s(cmp=True, frozen=True)
+class ConversionError(CSVError):
+    message = attr.ib(type=str)  # type: str
+    position = attr.ib(
+        None, type=Optional[Position]
+    )  # type: Optional[Position]
+    column = attr.ib(
+        None, type=Union[int, str, None]
+    )  # type: Union[int, str, None]
+    value = attr.ib(None, type=Optional[str])  # type: Optional[str]
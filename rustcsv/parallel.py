@@ -0,0 +1,18 @@
+import os
+from typing import Optional
+
+from ._rustcsv import CSVReader
+
+
+def read_parallel(path: str, threads: Optional[int] = None, **options):
+    """
+    Reads the CSV file at ``path`` with ``threads`` threads parsing it in
+    chunks, defaulting to the number of CPUs.
+
+    Returns a :class:`rustcsv.CSVReader`, which returns the records in order.
+    Any other ``options`` are passed to :class:`rustcsv.CSVReader`, e.g.
+    ``batch_size`` to read batches of records.
+    """
+    if threads is None:
+        threads = os.cpu_count() or 1
+    return CSVReader(path, threads=threads, **options)
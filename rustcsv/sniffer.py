@@ -0,0 +1,62 @@
+from typing import Any, Dict, Optional
+
+import attr
+
+from ._rustcsv import Dialect
+
+
+@attr.s(slots=True, cmp=True, frozen=True)
+class SniffedDialect:
+    """
+    The dialect of a CSV, as detected by :func:`rustcsv.sniff`.
+    """
+
+    delimiter = attr.ib(type=bytes)  # type: bytes
+    quotechar = attr.ib(type=bytes)  # type: bytes
+    escapechar = attr.ib(type=Optional[bytes])  # type: Optional[bytes]
+    doublequote = attr.ib(type=bool)  # type: bool
+    terminator = attr.ib(type=bytes)  # type: bytes
+    has_headers = attr.ib(type=bool)  # type: bool
+    columns = attr.ib(type=int)  # type: int
+
+    def reader_options(self) -> Dict[str, Any]:
+        """
+        Returns the keyword arguments of :class:`rustcsv.CSVReader` for
+        this dialect.
+        """
+        return dict(
+            delimiter=self.delimiter,
+            terminator=self.terminator,
+            quotechar=self.quotechar,
+            escapechar=self.escapechar,
+            doublequote=self.doublequote,
+            has_headers=self.has_headers,
+        )
+
+    def writer_options(self) -> Dict[str, Any]:
+        """
+        Returns the keyword arguments of :class:`rustcsv.CSVWriter` and
+        :class:`rustcsv.CSVDictWriter` for this dialect.
+        """
+        options = dict(
+            delimiter=self.delimiter,
+            terminator=self.terminator,
+            quotechar=self.quotechar,
+            double_quote=self.doublequote,
+        )
+        if self.escapechar is not None:
+            options["escape"] = self.escapechar
+        return options
+
+    def dialect(self) -> Dialect:
+        """
+        Returns the :class:`rustcsv.Dialect`, which doesn't include
+        :attr:`has_headers`.
+        """
+        return Dialect(
+            delimiter=self.delimiter,
+            terminator=self.terminator,
+            quotechar=self.quotechar,
+            escapechar=self.escapechar,
+            doublequote=self.doublequote,
+        )
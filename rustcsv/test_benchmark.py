@@ -21,6 +21,7 @@ class Parser(enum.Enum):
     STDLIB = "STDLIB"
     RUST = "RUST"
     RUST_NO_PY_READER = "RUST_NO_PY_READER"
+    RUST_THREADED = "RUST_THREADED"
 
 
 class FileStorage(enum.Enum):
@@ -43,6 +44,8 @@ def get_reader(impl: Parser, path: str) -> Iterable[Iterable[str]]:
         return CSVReader(open(path, "rb"))
     elif impl is Parser.RUST_NO_PY_READER:
         return CSVReader(path)
+    elif impl is Parser.RUST_THREADED:
+        return CSVReader(path, threaded=True)
     else:
         raise ValueError("Invalid impl: {impl}".format(impl=impl))
 
@@ -155,7 +158,13 @@ def read_csv(impl: Parser, path: str):
 
 @pytest.mark.benchmark(min_rounds=10)
 @pytest.mark.parametrize(
-    "impl", [Parser.RUST, Parser.RUST_NO_PY_READER, Parser.STDLIB]
+    "impl",
+    [
+        Parser.RUST,
+        Parser.RUST_NO_PY_READER,
+        Parser.RUST_THREADED,
+        Parser.STDLIB,
+    ],
 )
 def test_benchmark_read(
     benchmark: BenchmarkFixture, impl, fx_csv_file: CSVFixture
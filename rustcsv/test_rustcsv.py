@@ -1,12 +1,19 @@
+import bz2
 import contextlib
+import csv
+import decimal
+import functools
+import gzip
 import io
 import logging
+import lzma
+import mmap
 import tempfile
 from typing import Iterable, Union
 
 import pytest
 import rustcsv.error
-from rustcsv import CSVReader, CSVWriter
+from rustcsv import CSVDictWriter, CSVReader, CSVWriter
 
 _log = logging.getLogger(__name__)
 
@@ -212,3 +219,1740 @@ def test_writer_invalid_args():
     fd = io.BytesIO()
     with pytest.raises(ValueError):
         CSVWriter(fd, quote_style="invalid")
+
+
+@pytest.mark.parametrize(
+    "kwargs, records, expected",
+    [
+        (dict(), [{"a": "1", "b": "2"}], b"a,b\n1,2\n"),
+        (dict(), [{"b": "2", "a": "1"}], b"a,b\n1,2\n"),
+        (dict(), [{"a": "1"}], b"a,b\n1,\n"),
+        (dict(restval="NA"), [{"b": "2"}], b"a,b\nNA,2\n"),
+        (
+            dict(extrasaction="ignore"),
+            [{"a": "1", "b": "2", "c": "3"}],
+            b"a,b\n1,2\n",
+        ),
+    ],
+    ids=repr,
+)
+def test_dict_writer(kwargs, records, expected):
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        writer = CSVDictWriter(fd, fieldnames=["a", "b"], **kwargs)
+        assert writer.fieldnames == ["a", "b"]
+        writer.writeheader()
+        for row in records:
+            writer.writerow(row)
+
+        writer.flush()
+        result = open(fd.name, "rb").read()
+        assert result == expected
+
+
+def test_dict_writer_extra_keys():
+    writer = CSVDictWriter(io.BytesIO(), fieldnames=["a"])
+    with pytest.raises(ValueError):
+        writer.writerow({"a": "1", "b": "2"})
+
+
+def test_dict_writer_invalid_args():
+    with pytest.raises(ValueError):
+        CSVDictWriter(io.BytesIO(), fieldnames=["a"], extrasaction="invalid")
+
+
+@pytest.mark.parametrize(
+    "csv_content, kwargs, expected_fieldnames, expected",
+    [
+        pytest.param(
+            b"a,b\n" b"1,2\n" b"3,4\n",
+            dict(has_headers=True),
+            ["a", "b"],
+            [{"a": "1", "b": "2"}, {"a": "3", "b": "4"}],
+        ),
+        pytest.param(
+            b"1,2\n" b"3,4\n",
+            dict(fieldnames=["x", "y"]),
+            ["x", "y"],
+            [{"x": "1", "y": "2"}, {"x": "3", "y": "4"}],
+        ),
+        pytest.param(
+            b"a,b\n" b"1,2\n",
+            dict(has_headers=True, fieldnames=["x", "y"]),
+            ["x", "y"],
+            [{"x": "1", "y": "2"}],
+        ),
+        pytest.param(b"", dict(has_headers=True), None, []),
+        pytest.param(
+            b"1,2\n",
+            dict(fieldnames=["x", "y", "z"]),
+            ["x", "y", "z"],
+            [],
+            marks=pytest.mark.xfail(
+                raises=rustcsv.error.UnequalLengthsError, strict=True
+            ),
+        ),
+    ],
+    ids=repr,
+)
+def test_reader_fieldnames(csv_content, kwargs, expected_fieldnames, expected):
+    csv_reader = CSVReader(io.BytesIO(csv_content), **kwargs)
+    assert csv_reader.fieldnames == expected_fieldnames
+    result = list(csv_reader)
+    assert result == expected
+
+
+def test_reader_fieldnames_default():
+    csv_reader = CSVReader(io.BytesIO(b"a,b\n"))
+    assert csv_reader.fieldnames is None
+    assert list(csv_reader) == [("a", "b")]
+
+
+@pytest.mark.parametrize(
+    "csv_content, kwargs, expected",
+    [
+        pytest.param(
+            b"'a,b',c\n", dict(quotechar=b"'"), [("a,b", "c")], id="quotechar"
+        ),
+        pytest.param(
+            b'"a\\"b",c\n',
+            dict(escapechar=b"\\", doublequote=False),
+            [('a"b', "c")],
+            id="escapechar",
+        ),
+        pytest.param(
+            b'"a",b\n', dict(quoting=False), [('"a"', "b")], id="quoting"
+        ),
+        pytest.param(
+            b"#a,b\n" b"c,d\n",
+            dict(comment=b"#"),
+            [("c", "d")],
+            id="comment",
+        ),
+        pytest.param(
+            b" a , b \n", dict(trim="fields"), [("a", "b")], id="trim"
+        ),
+        pytest.param(
+            b" a , b \n" b" c , d \n",
+            dict(trim="headers", has_headers=True),
+            [{"a": " c ", "b": " d "}],
+            id="trim-headers",
+        ),
+        pytest.param(
+            b"a,b\n" b"c\n", dict(flexible=True), [("a", "b"), ("c",)]
+        ),
+        pytest.param(
+            b"a,b\n" b"c\n" b"d,e,f\n",
+            dict(flexible=True, has_headers=True),
+            [{"a": "c", "b": None}, {"a": "d", "b": "e", None: ["f"]}],
+            id="flexible-dict",
+        ),
+    ],
+)
+def test_reader_dialect(csv_content, kwargs, expected):
+    assert list(CSVReader(io.BytesIO(csv_content), **kwargs)) == expected
+
+
+@pytest.mark.parametrize(
+    "kwargs",
+    [
+        dict(quotechar=b"''"),
+        dict(escapechar=b""),
+        dict(comment=b"##"),
+        dict(trim="invalid"),
+    ],
+    ids=repr,
+)
+def test_reader_invalid_dialect(kwargs):
+    with pytest.raises(ValueError):
+        CSVReader(io.BytesIO(b""), **kwargs)
+
+
+@pytest.mark.parametrize(
+    "kwargs, records, expected",
+    [
+        (dict(delimiter=b"\t"), [("a", "b")], b"a\tb\n"),
+        (dict(delimiter=b"|"), [("a|b", "c")], b'"a|b"|c\n'),
+        (dict(quotechar=b"'"), [("a,b", "c")], b"'a,b',c\n"),
+        (dict(comment=b"#"), [("#a", "b")], b'"#a",b\n'),
+        (dict(flexible=True), [("a", "b"), ("c",)], b"a,b\nc\n"),
+    ],
+    ids=repr,
+)
+def test_writer_dialect(kwargs, records, expected):
+    fd = io.BytesIO()
+    writer = CSVWriter(fd, **kwargs)
+    for row in records:
+        writer.writerow(row)
+    writer.flush()
+    assert fd.getvalue() == expected
+
+
+@pytest.mark.parametrize(
+    "terminator, byte", [(b"\n", 8), (b"\r", 8), (b"\r\n", 10)], ids=repr
+)
+def test_writer_unequal_lengths(terminator, byte):
+    fd = io.BytesIO()
+    writer = CSVWriter(fd, terminator=terminator)
+    writer.writerow(("a", "b"))
+    writer.writerow(("c", "d"))
+    with pytest.raises(rustcsv.error.UnequalLengthsError) as exc_info:
+        writer.writerow(("e",))
+
+    assert exc_info.value.position == rustcsv.error.Position(
+        byte=byte, line=3, record=2
+    )
+    writer.flush()
+    assert fd.getvalue() == b"a,b" + terminator + b"c,d" + terminator
+
+
+@pytest.mark.parametrize(
+    "csv_content, kwargs, expected",
+    [
+        pytest.param(
+            b"a,\xa0\xa1\n", dict(), [(b"a", b"\xa0\xa1")], id="tuple"
+        ),
+        pytest.param(
+            b"a,b\n" b"\xff,c\n",
+            dict(has_headers=True),
+            [{"a": b"\xff", "b": b"c"}],
+            id="dict",
+        ),
+    ],
+)
+def test_reader_bytes_mode(csv_content, kwargs, expected):
+    csv_reader = CSVReader(io.BytesIO(csv_content), bytes_mode=True, **kwargs)
+    assert list(csv_reader) == expected
+
+
+@pytest.mark.parametrize(
+    "csv_content, encoding, expected",
+    [
+        pytest.param(
+            "å,ä\n".encode("cp1252"), "windows-1252", [("å", "ä")]
+        ),
+        pytest.param("å,ä\n".encode("latin-1"), "latin-1", [("å", "ä")]),
+        pytest.param(
+            "あ,い\n".encode("shift_jis"), "shift_jis", [("あ", "い")]
+        ),
+        pytest.param(
+            "å,ä\n".encode("utf-16"), "utf-16", [("å", "ä")], id="utf-16-bom"
+        ),
+        pytest.param(
+            "å,ä\n".encode("utf-8-sig"),
+            "windows-1252",
+            [("å", "ä")],
+            id="bom-override",
+        ),
+    ],
+)
+def test_reader_encoding(csv_content, encoding, expected):
+    csv_reader = CSVReader(io.BytesIO(csv_content), encoding=encoding)
+    assert list(csv_reader) == expected
+
+
+def test_reader_encoding_from_path():
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write("a,b\nå,ä\n".encode("cp1252"))
+        fd.flush()
+        csv_reader = CSVReader(fd.name, encoding="cp1252", has_headers=True)
+        assert list(csv_reader) == [{"a": "å", "b": "ä"}]
+
+
+def test_reader_encoding_errors():
+    csv_content = b"a,\x81\n"
+    with pytest.raises(rustcsv.error.UTF8Error):
+        list(CSVReader(io.BytesIO(csv_content), encoding="shift_jis"))
+
+    csv_reader = CSVReader(
+        io.BytesIO(csv_content), encoding="shift_jis", errors="replace"
+    )
+    assert list(csv_reader) == [("a", "\ufffd")]
+
+
+def test_reader_invalid_encoding():
+    with pytest.raises(LookupError):
+        CSVReader(io.BytesIO(b""), encoding="invalid")
+
+
+@pytest.mark.parametrize(
+    "errors, expected",
+    [
+        ("replace", [("a", "\ufffd\ufffdb")]),
+        ("ignore", [("a", "b")]),
+        ("surrogateescape", [("a", "\udca0\udca1b")]),
+        ("backslashreplace", [("a", "\\xa0\\xa1b")]),
+    ],
+)
+def test_reader_errors(errors, expected):
+    csv_reader = CSVReader(io.BytesIO(b"a,\xa0\xa1b\n"), errors=errors)
+    assert list(csv_reader) == expected
+
+
+def test_reader_errors_strict():
+    with pytest.raises(rustcsv.error.UTF8Error):
+        list(CSVReader(io.BytesIO(b"a,\xa0\xa1b\n"), errors="strict"))
+
+
+def test_reader_invalid_errors():
+    with pytest.raises(LookupError):
+        CSVReader(io.BytesIO(b""), errors="invalid")
+
+
+def test_surrogateescape_round_trip():
+    csv_content = b"valid: \xf0\x9f\x90\x8d,invalid: \xa0\xa1\n"
+    rows = list(CSVReader(io.BytesIO(csv_content), errors="surrogateescape"))
+
+    fd = io.BytesIO()
+    writer = CSVWriter(fd, errors="surrogateescape")
+    for row in rows:
+        writer.writerow(row)
+    writer.flush()
+    assert fd.getvalue() == csv_content
+
+
+@pytest.mark.parametrize(
+    "make_writer, row",
+    [
+        (CSVWriter, ("\udca0",)),
+        (functools.partial(CSVDictWriter, fieldnames=["a"]), {"a": "\udca0"}),
+    ],
+    ids=repr,
+)
+def test_writer_errors(make_writer, row):
+    with pytest.raises(UnicodeEncodeError):
+        make_writer(io.BytesIO()).writerow(row)
+
+    fd = io.BytesIO()
+    writer = make_writer(fd, errors="replace")
+    writer.writerow(row)
+    writer.flush()
+    assert fd.getvalue() == b"?\n"
+
+    with pytest.raises(LookupError):
+        make_writer(io.BytesIO(), errors="invalid")
+
+
+def test_reader_read_batch():
+    csv_reader = CSVReader(io.BytesIO(b"a\n" b"b\n" b"c\n"))
+    assert csv_reader.read_batch(2) == [("a",), ("b",)]
+    assert csv_reader.read_batch(2) == [("c",)]
+    assert csv_reader.read_batch(2) == []
+
+
+@pytest.mark.parametrize(
+    "csv_content, kwargs, expected",
+    [
+        pytest.param(
+            b"a\n" b"b\n" b"c\n",
+            dict(batch_size=2),
+            [[("a",), ("b",)], [("c",)]],
+        ),
+        pytest.param(b"", dict(batch_size=2), []),
+        pytest.param(
+            b"x\n" b"a\n" b"b\n",
+            dict(batch_size=5, has_headers=True),
+            [[{"x": "a"}, {"x": "b"}]],
+        ),
+    ],
+    ids=repr,
+)
+def test_reader_batch_size(csv_content, kwargs, expected):
+    assert list(CSVReader(io.BytesIO(csv_content), **kwargs)) == expected
+
+
+def test_reader_invalid_batch_size():
+    with pytest.raises(ValueError):
+        CSVReader(io.BytesIO(b""), batch_size=0)
+
+
+@pytest.mark.parametrize("kwargs", [dict(), dict(has_headers=True)], ids=repr)
+def test_reader_threaded(kwargs):
+    records = [
+        "{i},{text}".format(i=i, text="æ" * (i % 42)).encode("utf-8")
+        for i in range(10 * 1000)
+    ]
+    with byte_records(records) as fd:
+        expected = list(CSVReader(fd.name, **kwargs))
+        result = list(CSVReader(fd.name, threaded=True, **kwargs))
+        assert result == expected
+
+
+def test_reader_threaded_file_object():
+    with pytest.raises(ValueError):
+        CSVReader(io.BytesIO(b""), threaded=True)
+
+
+class ReadOnlyFile:
+    """
+    Binary file-like object without a ``readinto`` method.
+    """
+
+    def __init__(self, content: bytes):
+        self._fd = io.BytesIO(content)
+
+    def read(self, size: int = -1) -> bytes:
+        return self._fd.read(size)
+
+
+class ReadIntoFile(ReadOnlyFile):
+    """
+    Binary file-like object that records the ``readinto`` calls.
+    """
+
+    def __init__(self, content: bytes):
+        super().__init__(content)
+        self.readinto_calls = 0
+
+    def readinto(self, buffer) -> int:
+        self.readinto_calls += 1
+        return self._fd.readinto(buffer)
+
+
+def test_reader_read_fallback():
+    csv_content = b"".join(b"%d,abc\n" % i for i in range(10 * 1000))
+    result = list(CSVReader(ReadOnlyFile(csv_content)))
+    assert result == list(CSVReader(io.BytesIO(csv_content)))
+
+
+def test_reader_readinto():
+    csv_content = b"".join(b"%d,abc\n" % i for i in range(10 * 1000))
+    fd = ReadIntoFile(csv_content)
+    result = list(CSVReader(fd))
+    assert fd.readinto_calls > 0
+    assert result == list(CSVReader(io.BytesIO(csv_content)))
+
+
+def test_reader_readinto_invalid_size():
+    class InvalidReadIntoFile(ReadIntoFile):
+        def readinto(self, buffer) -> int:
+            return super().readinto(buffer) + len(buffer)
+
+    with pytest.raises(OSError):
+        list(CSVReader(InvalidReadIntoFile(b"a,b\n")))
+
+
+def test_reader_fileno_honors_position():
+    records = [b"%d,abc" % i for i in range(10 * 1000)]
+    with byte_records(records) as fd:
+        # Let the buffered file object read ahead
+        assert fd.readline() == b"0,abc\n"
+        result = list(CSVReader(fd))
+        assert result == [(str(i), "abc") for i in range(1, 10 * 1000)]
+
+
+@pytest.mark.parametrize(
+    "open_compressed",
+    [gzip.open, bz2.open, lzma.open],
+    ids=lambda f: f.__module__,
+)
+def test_reader_fileno_compressed_file_object(open_compressed):
+    records = [b"%d,abc" % i for i in range(10 * 1000)]
+    with tempfile.NamedTemporaryFile() as fd:
+        with open_compressed(fd.name, "wb") as writable:
+            writable.write(b"".join(record + b"\n" for record in records))
+        with open_compressed(fd.name, "rb") as readable:
+            assert readable.readline() == b"0,abc\n"
+            result = list(CSVReader(readable))
+    assert result == [(str(i), "abc") for i in range(1, 10 * 1000)]
+
+
+def test_reader_fileno_pipe():
+    import os
+    import threading
+
+    csv_content = b"".join(b"%d,abc\n" % i for i in range(10 * 1000))
+    read_fd, write_fd = os.pipe()
+
+    def write():
+        with os.fdopen(write_fd, "wb") as w:
+            w.write(csv_content)
+
+    writer_thread = threading.Thread(target=write)
+    writer_thread.start()
+    with os.fdopen(read_fd, "rb") as r:
+        # Let the buffered file object read ahead
+        assert r.readline() == b"0,abc\n"
+        result = list(CSVReader(r))
+    writer_thread.join()
+    assert result == [(str(i), "abc") for i in range(1, 10 * 1000)]
+
+
+@pytest.mark.parametrize(
+    "csv_content, kwargs, expected",
+    [
+        pytest.param(
+            b"1,2.5,true,1.10,x\n",
+            dict(dtypes={0: "int", 1: "float", 2: "bool", 3: "decimal"}),
+            [(1, 2.5, True, decimal.Decimal("1.10"), "x")],
+            id="by-index",
+        ),
+        pytest.param(
+            b"a,b,c\n" b"1,0,x\n",
+            dict(has_headers=True, dtypes={"a": int, "b": bool, "c": str}),
+            [{"a": 1, "b": False, "c": "x"}],
+            id="by-name",
+        ),
+        pytest.param(
+            b"123456789012345678901234567890\n",
+            dict(dtypes={0: "int"}),
+            [(123456789012345678901234567890,)],
+            id="big-int",
+        ),
+        pytest.param(
+            b"1.5\n",
+            dict(dtypes={0: decimal.Decimal}),
+            [(decimal.Decimal("1.5"),)],
+            id="decimal-type",
+        ),
+    ],
+)
+def test_reader_dtypes(csv_content, kwargs, expected):
+    result = list(CSVReader(io.BytesIO(csv_content), **kwargs))
+    assert result == expected
+    assert [type(v) for v in result[0]] == [type(v) for v in expected[0]]
+
+
+@pytest.mark.parametrize(
+    "csv_content, kwargs, column",
+    [
+        (b"1\n" b"x\n", dict(dtypes={0: "int"}), 0),
+        (b"a\n" b"1\n" b"x\n", dict(has_headers=True, dtypes={"a": "int"}), "a"),
+    ],
+    ids=repr,
+)
+def test_reader_dtypes_conversion_error(csv_content, kwargs, column):
+    with pytest.raises(rustcsv.error.ConversionError) as exc_info:
+        list(CSVReader(io.BytesIO(csv_content), **kwargs))
+
+    error = exc_info.value  # type: rustcsv.error.ConversionError
+    assert error.column == column
+    assert error.value == "x"
+    assert error.position is not None
+    assert error.position.line == csv_content.count(b"\n")
+
+
+@pytest.mark.parametrize(
+    "kwargs, exception",
+    [
+        (dict(dtypes={0: "invalid"}), ValueError),
+        (dict(dtypes={"a": "int"}), KeyError),
+        (dict(dtypes={0: "int"}, bytes_mode=True), ValueError),
+    ],
+    ids=repr,
+)
+def test_reader_invalid_dtypes(kwargs, exception):
+    with pytest.raises(exception):
+        CSVReader(io.BytesIO(b""), **kwargs)
+
+
+@pytest.mark.parametrize(
+    "csv_content, kwargs, expected",
+    [
+        pytest.param(
+            b'a,NULL,"",\\N\n',
+            dict(na_values=["", "NULL", "\\N"]),
+            [("a", None, None, None)],
+            id="tuple",
+        ),
+        pytest.param(
+            b'a,"",,"NULL",NULL\n',
+            dict(na_values=["", "NULL"], quoted_na=False),
+            [("a", "", None, "NULL", None)],
+            id="quoted_na=False",
+        ),
+        pytest.param(
+            b'a,b\n' b'"",\n' b',""\n',
+            dict(has_headers=True, na_values=[""], quoted_na=False),
+            [{"a": "", "b": None}, {"a": None, "b": ""}],
+            id="quoted_na=False-dict",
+        ),
+        pytest.param(
+            b'1,NA,x\n' b'NA,2.5,NA\n',
+            dict(na_values=["NA"], dtypes={0: "int", 1: "float"}),
+            [(1, None, "x"), (None, 2.5, None)],
+            id="dtypes",
+        ),
+        pytest.param(
+            b'a,,""\n',
+            dict(na_values=[""], quoted_na=False, bytes_mode=True),
+            [(b"a", None, b"")],
+            id="bytes_mode",
+        ),
+        pytest.param(
+            b'a,b\r\n' b'"",\r\n' b',""\r\n',
+            dict(
+                has_headers=True,
+                na_values=[""],
+                quoted_na=False,
+                terminator=b"\r\n",
+            ),
+            [{"a": "", "b": None}, {"a": None, "b": ""}],
+            id="quoted_na=False-crlf",
+        ),
+        pytest.param(
+            b'"",\r' b',""\n' b'"",\r\n',
+            dict(na_values=[""], quoted_na=False, terminator=b"\r\n"),
+            [("", None), (None, ""), ("", None)],
+            id="quoted_na=False-cr",
+        ),
+        pytest.param(
+            b'"\\"",\n' b',""\n',
+            dict(na_values=[""], quoted_na=False, escapechar=b"\\"),
+            [('"', None), (None, "")],
+            id="quoted_na=False-escape",
+        ),
+        pytest.param(
+            b'#"\n' b'"",\n' b'\n' b',""\n',
+            dict(na_values=[""], quoted_na=False, comment=b"#"),
+            [("", None), (None, "")],
+            id="quoted_na=False-comment",
+        ),
+    ],
+)
+def test_reader_na_values(csv_content, kwargs, expected):
+    assert list(CSVReader(io.BytesIO(csv_content), **kwargs)) == expected
+
+
+def test_reader_na_values_quoted_na_threaded():
+    with tempfile.NamedTemporaryFile() as tmp:
+        tmp.write(b'"",\n' * 10000)
+        tmp.flush()
+        reader = CSVReader(
+            tmp.name, na_values=[""], quoted_na=False, threaded=True
+        )
+        assert list(reader) == [("", None)] * 10000
+
+
+def test_reader_na_values_quoted_na_after_error():
+    reader = CSVReader(
+        io.BytesIO(b"a,b\n" b"1\n" b'"",\n'),
+        has_headers=True,
+        na_values=[""],
+        quoted_na=False,
+    )
+    with pytest.raises(rustcsv.error.UnequalLengthsError):
+        next(reader)
+
+    assert list(reader) == [{"a": "", "b": None}]
+
+
+@pytest.fixture()
+def pyarrow():
+    return pytest.importorskip("pyarrow")
+
+
+def test_read_arrow(pyarrow):
+    table = rustcsv.read_arrow(
+        io.BytesIO(b"a,b\n" b"x,1\n" b"y,2\n" b"z,3\n"), batch_size=2
+    )
+    assert table.schema.names == ["a", "b"]
+    assert table.to_pydict() == {"a": ["x", "y", "z"], "b": ["1", "2", "3"]}
+    assert [len(batch) for batch in table.to_batches()] == [2, 1]
+
+
+def test_read_arrow_schema(pyarrow):
+    schema = pyarrow.schema(
+        [
+            ("s", pyarrow.string()),
+            ("i", pyarrow.int64()),
+            ("f", pyarrow.float64()),
+            ("b", pyarrow.bool_()),
+        ]
+    )
+    table = rustcsv.read_arrow(
+        io.BytesIO(b"s,i,f,b\n" b"x,1,1.5,true\n" b",,,\n"), schema=schema
+    )
+    assert table.schema == schema
+    assert table.to_pydict() == {
+        "s": ["x", ""],
+        "i": [1, None],
+        "f": [1.5, None],
+        "b": [True, None],
+    }
+
+
+def test_read_arrow_no_headers(pyarrow):
+    table = rustcsv.read_arrow(io.BytesIO(b"x,y\n"), has_headers=False)
+    assert table.to_pydict() == {"f0": ["x"], "f1": ["y"]}
+
+
+def test_read_arrow_empty(pyarrow):
+    schema = pyarrow.schema([("i", pyarrow.int64())])
+    table = rustcsv.read_arrow(io.BytesIO(b"i\n"), schema=schema)
+    assert table.num_rows == 0
+    assert table.schema == schema
+
+
+def test_read_arrow_conversion_error(pyarrow):
+    schema = pyarrow.schema([("i", pyarrow.int64())])
+    with pytest.raises(rustcsv.error.ConversionError) as exc_info:
+        rustcsv.read_arrow(io.BytesIO(b"i\n" b"1\n" b"x\n"), schema=schema)
+
+    assert exc_info.value.column == "i"
+    assert exc_info.value.value == "x"
+    assert exc_info.value.position.line == 3
+
+
+def test_read_arrow_not_nullable(pyarrow):
+    schema = pyarrow.schema(
+        [
+            pyarrow.field("s", pyarrow.string(), nullable=False),
+            pyarrow.field("i", pyarrow.int64(), nullable=False),
+        ]
+    )
+    table = rustcsv.read_arrow(io.BytesIO(b"s,i\n" b",1\n"), schema=schema)
+    assert table.schema == schema
+    assert table.to_pydict() == {"s": [""], "i": [1]}
+
+    with pytest.raises(rustcsv.error.ConversionError) as exc_info:
+        rustcsv.read_arrow(
+            io.BytesIO(b"s,i\n" b"x,1\n" b"y,\n"), schema=schema
+        )
+    assert exc_info.value.column == "i"
+    assert exc_info.value.position.line == 3
+
+
+@pytest.fixture()
+def numpy():
+    return pytest.importorskip("numpy")
+
+
+def test_read_numpy(numpy):
+    array = rustcsv.read_numpy(io.BytesIO(b"1,2.5\n" b"3, 4\n"))
+    assert array.dtype == numpy.float64
+    assert array.tolist() == [[1.0, 2.5], [3.0, 4.0]]
+
+
+def test_read_numpy_usecols(numpy):
+    array = rustcsv.read_numpy(
+        io.BytesIO(b"a,b,c\n" b"1,x,3\n" b"4,y,6\n"),
+        dtype=numpy.int32,
+        usecols=["c", 0],
+        has_headers=True,
+    )
+    assert array.dtype == numpy.int32
+    assert array.tolist() == [[3, 1], [6, 4]]
+
+
+def test_read_numpy_structured(numpy):
+    dtype = numpy.dtype([("t", "i8"), ("v", "f4"), ("ok", "?")])
+    array = rustcsv.read_numpy(
+        io.BytesIO(b"1,0.5,true\n" b"2,1.5,0\n"), dtype=dtype
+    )
+    assert array.dtype == dtype
+    assert array.tolist() == [(1, 0.5, True), (2, 1.5, False)]
+
+
+def test_read_numpy_structured_titles(numpy):
+    dtype = numpy.dtype([(("time", "t"), "i8"), (("value", "v"), "f8")])
+    array = rustcsv.read_numpy(io.BytesIO(b"1,0.5\n"), dtype=dtype)
+    assert array.dtype == dtype
+    assert array.tolist() == [(1, 0.5)]
+
+
+def test_read_numpy_many_rows(numpy):
+    csv_content = b"".join(b"%d,%d\n" % (i, -i) for i in range(5000))
+    array = rustcsv.read_numpy(io.BytesIO(csv_content), dtype=numpy.int64)
+    assert array.shape == (5000, 2)
+    assert array.tolist() == [[i, -i] for i in range(5000)]
+
+
+def test_read_numpy_empty(numpy):
+    assert rustcsv.read_numpy(io.BytesIO(b"")).shape == (0, 0)
+
+
+def test_read_numpy_conversion_error(numpy):
+    with pytest.raises(rustcsv.error.ConversionError) as exc_info:
+        rustcsv.read_numpy(io.BytesIO(b"1,2\n" b"3,x\n"))
+
+    assert exc_info.value.column == 1
+    assert exc_info.value.value == "x"
+    assert exc_info.value.position == rustcsv.error.Position(
+        byte=4, line=2, record=1
+    )
+
+
+def test_read_numpy_unequal_lengths(numpy):
+    with pytest.raises(rustcsv.error.UnequalLengthsError):
+        rustcsv.read_numpy(io.BytesIO(b"1,2\n" b"3\n"))
+
+
+@pytest.mark.parametrize(
+    "csv_content, kwargs, expected",
+    [
+        pytest.param(
+            b"a,b\n" b"1,x\n" b"2,y\n" b"3,z\n",
+            dict(has_headers=True, batch_size=2),
+            [{"a": ["1", "2"], "b": ["x", "y"]}, {"a": ["3"], "b": ["z"]}],
+            id="fieldnames",
+        ),
+        pytest.param(
+            b"1,x\n" b"2,y\n",
+            dict(),
+            [{0: ["1", "2"], 1: ["x", "y"]}],
+            id="indices",
+        ),
+        pytest.param(
+            b"a,b\n" b"1,\n" b"NA,y\n",
+            dict(has_headers=True, dtypes={"a": int}, na_values=["", "NA"]),
+            [{"a": [1, None], "b": [None, "y"]}],
+            id="dtypes-na_values",
+        ),
+        pytest.param(
+            b"1\n" b"2,y,z\n",
+            dict(fieldnames=["a", "b"], flexible=True),
+            [{"a": ["1", "2"], "b": [None, "y"], 2: [None, "z"]}],
+            id="flexible",
+        ),
+    ],
+)
+def test_reader_columnar(csv_content, kwargs, expected):
+    reader = CSVReader(io.BytesIO(csv_content), columnar=True, **kwargs)
+    assert list(reader) == expected
+
+
+def test_reader_columnar_read_batch():
+    reader = CSVReader(
+        io.BytesIO(b"a\n" b"1\n" b"2\n"), has_headers=True, columnar=True
+    )
+    assert reader.read_batch(1) == {"a": ["1"]}
+    assert reader.read_batch(5) == {"a": ["2"]}
+    assert reader.read_batch(5) == {}
+
+
+def test_reader_skip_lines():
+    reader = CSVReader(
+        io.BytesIO(b"# exported\n" b"\n" b"a,b\n" b"1,2\n"),
+        has_headers=True,
+        skip_lines=2,
+    )
+    assert list(reader) == [{"a": "1", "b": "2"}]
+
+
+def test_reader_skip_lines_terminator():
+    reader = CSVReader(
+        io.BytesIO(
+            b'"exported\r\nat noon"\r\n' b"\r\n" b"a,b\r\n" b"1,2\r\n"
+        ),
+        terminator=b"\r\n",
+        has_headers=True,
+        skip_lines=2,
+        with_position=True,
+    )
+    assert list(reader) == [
+        (
+            rustcsv.error.Position(byte=5, line=2, record=1),
+            {"a": "1", "b": "2"},
+        )
+    ]
+
+
+@pytest.fixture()
+def pandas():
+    return pytest.importorskip("pandas")
+
+
+def test_read_dataframe(pandas):
+    df = rustcsv.read_dataframe(
+        io.BytesIO(b"a,b,c\n" b"1,x,2018-01-02\n" b"NA,,2018-01-03\n"),
+        parse_dates=["c"],
+    )
+    assert list(df.columns) == ["a", "b", "c"]
+    assert df["a"].tolist()[0] == "1"
+    assert df["a"].isna().tolist() == [False, True]
+    assert df["b"].isna().tolist() == [False, True]
+    assert df["c"].tolist() == [
+        pandas.Timestamp("2018-01-02"),
+        pandas.Timestamp("2018-01-03"),
+    ]
+
+
+def test_read_dataframe_options(pandas):
+    df = rustcsv.read_dataframe(
+        io.BytesIO(
+            b"preamble\n" b"skipped\n" b"a;b;c\n" b"1;1.5;x\n" b"2;-;y\n"
+            b"3;3.5;z\n"
+        ),
+        sep=";",
+        skiprows=1,
+        header=1,
+        usecols=["b", 0],
+        dtype={"a": int, "b": "float64"},
+        na_values=["-"],
+        nrows=2,
+    )
+    assert list(df.columns) == ["b", "a"]
+    assert df["a"].tolist() == [1, 2]
+    assert df["a"].dtype == "int64"
+    assert df["b"].dtype == "float64"
+    assert df["b"].isna().tolist() == [False, True]
+
+
+@pytest.mark.parametrize("column", ["a", 0], ids=repr)
+def test_read_dataframe_int_missing(pandas, column):
+    df = rustcsv.read_dataframe(
+        io.BytesIO(b"a,b\n" b"1,x\n" b"NA,y\n" b"3,z\n"),
+        dtype={column: int},
+    )
+    assert df["a"].dtype == "float64"
+    assert df["a"].isna().tolist() == [False, True, False]
+    assert df["a"].tolist()[::2] == [1.0, 3.0]
+
+
+def test_read_dataframe_no_header(pandas):
+    df = rustcsv.read_dataframe(
+        io.BytesIO(b"1,2\n" b"3,4\n"), header=None, dtype="int32"
+    )
+    assert list(df.columns) == [0, 1]
+    assert df[1].tolist() == [2, 4]
+    assert df[1].dtype == "int32"
+
+
+def test_read_dataframe_str_dtype(pandas):
+    df = rustcsv.read_dataframe(io.BytesIO(b"a,b\n" b"1,NA\n"), dtype=str)
+    assert df["a"].tolist() == ["1"]
+    assert df["b"].isna().tolist() == [True]
+
+
+def test_reader_position():
+    reader = CSVReader(
+        io.BytesIO(b"a,b\n" b"1,2\n" b'"x\ny",3\n' b"4,5\n"), has_headers=True
+    )
+    assert reader.position is None
+    next(reader)
+    assert reader.position == rustcsv.error.Position(byte=4, line=2, record=1)
+    next(reader)
+    assert reader.position == rustcsv.error.Position(byte=8, line=3, record=2)
+    reader.read_batch(10)
+    assert reader.position == rustcsv.error.Position(
+        byte=16, line=5, record=3
+    )
+
+
+def test_reader_with_position():
+    reader = CSVReader(io.BytesIO(b"a\n" b"b\n"), with_position=True)
+    assert list(reader) == [
+        (rustcsv.error.Position(byte=0, line=1, record=0), ("a",)),
+        (rustcsv.error.Position(byte=2, line=2, record=1), ("b",)),
+    ]
+
+
+def test_reader_with_position_columnar():
+    with pytest.raises(ValueError):
+        CSVReader(io.BytesIO(b""), with_position=True, columnar=True)
+
+
+@pytest.mark.parametrize("source_type", ["path", "BytesIO", "file"], ids=repr)
+def test_reader_seek(source_type):
+    csv_content = b"a,b\n" b"1,2\n" b'"x\ny",3\n' b"4,5\n"
+    with contextlib.ExitStack() as stack:
+        fd = stack.enter_context(tempfile.NamedTemporaryFile("wb"))
+        fd.write(csv_content)
+        fd.flush()
+        if source_type == "path":
+            source = fd.name
+        elif source_type == "BytesIO":
+            source = io.BytesIO(csv_content)
+        else:
+            source = open(fd.name, "rb")
+            stack.callback(source.close)
+
+        reader = CSVReader(source, has_headers=True)
+        next(reader)
+        position = reader.position
+        assert list(reader) == [{"a": "x\ny", "b": "3"}, {"a": "4", "b": "5"}]
+
+        reader.seek(position)
+        assert reader.position is None
+        assert list(reader) == [
+            {"a": "1", "b": "2"},
+            {"a": "x\ny", "b": "3"},
+            {"a": "4", "b": "5"},
+        ]
+        assert reader.position == rustcsv.error.Position(
+            byte=16, line=5, record=3
+        )
+
+
+@pytest.mark.parametrize("source_type", ["BytesIO", "file"], ids=repr)
+def test_reader_seek_file_offset(source_type):
+    preamble = b"not csv\n"
+    csv_content = b"a,b\n" b"1,2\n" b"3,4\n"
+    with contextlib.ExitStack() as stack:
+        fd = stack.enter_context(tempfile.NamedTemporaryFile("wb"))
+        fd.write(preamble + csv_content)
+        fd.flush()
+        if source_type == "BytesIO":
+            source = io.BytesIO(preamble + csv_content)
+        else:
+            source = open(fd.name, "rb")
+            stack.callback(source.close)
+        source.readline()
+
+        reader = CSVReader(source, has_headers=True)
+        next(reader)
+        position = reader.position
+        assert position.byte == 4
+        assert list(reader) == [{"a": "3", "b": "4"}]
+
+        reader.seek(position)
+        assert list(reader) == [{"a": "1", "b": "2"}, {"a": "3", "b": "4"}]
+
+
+def test_reader_seek_unsupported():
+    reader = CSVReader(io.BytesIO(b"a\n"), skip_lines=1)
+    with pytest.raises(ValueError):
+        reader.seek(rustcsv.error.Position(byte=0, line=1, record=0))
+
+
+def test_build_index():
+    csv_content = b"a,b\n" b"1,2\n" b'"x\ny",3\n' b"4,5\n"
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        with tempfile.NamedTemporaryFile("rb") as index_fd:
+            assert rustcsv.build_index(fd.name, index_fd.name) == 4
+
+            position = rustcsv.index_position(index_fd.name, 2)
+            assert position == rustcsv.error.Position(
+                byte=8, line=None, record=2
+            )
+            with pytest.raises(IndexError):
+                rustcsv.index_position(index_fd.name, 4)
+
+        reader = CSVReader(fd.name, has_headers=True)
+        reader.seek(position)
+        assert list(reader) == [{"a": "x\ny", "b": "3"}, {"a": "4", "b": "5"}]
+        assert reader.position == rustcsv.error.Position(
+            byte=16, line=None, record=3
+        )
+
+        reader.seek(rustcsv.error.Position(byte=4, line=2, record=1))
+        next(reader)
+        assert reader.position == rustcsv.error.Position(
+            byte=4, line=2, record=1
+        )
+
+
+def test_build_index_unequal_lengths():
+    csv_content = b"a,b\n" b"1,2\n" b"3\n"
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        with tempfile.NamedTemporaryFile("rb") as index_fd:
+            rustcsv.build_index(fd.name, index_fd.name)
+            position = rustcsv.index_position(index_fd.name, 1)
+
+        reader = CSVReader(fd.name, has_headers=True)
+        reader.seek(position)
+        next(reader)
+        with pytest.raises(rustcsv.error.UnequalLengthsError) as exc_info:
+            next(reader)
+    assert exc_info.value.position == rustcsv.error.Position(
+        byte=8, line=None, record=2
+    )
+
+
+def test_build_index_dialect():
+    csv_content = b"a;b\r\n" b"1;2\r\n" b"3;4\r\n"
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        with tempfile.NamedTemporaryFile("rb") as index_fd:
+            count = rustcsv.build_index(
+                fd.name, index_fd.name, dialect="excel", delimiter=b";"
+            )
+            assert count == 3
+            position = rustcsv.index_position(index_fd.name, 2)
+            assert position.byte == 9
+
+        reader = CSVReader(
+            fd.name, has_headers=True, dialect="excel", delimiter=b";"
+        )
+        reader.seek(position)
+        assert list(reader) == [{"a": "3", "b": "4"}]
+
+
+def test_build_index_compressed():
+    with tempfile.NamedTemporaryFile("wb", suffix=".csv.gz") as fd:
+        fd.write(gzip.compress(b"a,b\n"))
+        fd.flush()
+        with pytest.raises(ValueError):
+            rustcsv.build_index(fd.name, fd.name + ".idx")
+
+
+@pytest.mark.parametrize(
+    "csv_content, options",
+    [
+        pytest.param(
+            b"".join(b"%d,row %d\n" % (i, i) for i in range(100)),
+            dict(),
+            id="no-quotes",
+        ),
+        pytest.param(
+            b"a,b\n"
+            + b"".join(
+                b'%d,"multi\nline, ""%d"""\n' % (i, i) for i in range(100)
+            ),
+            dict(has_headers=True),
+            id="quoted-newlines",
+        ),
+        pytest.param(
+            b'a,b\n1,"""\n2,x\n' * 20, dict(has_headers=True), id="ambiguous"
+        ),
+        pytest.param(
+            b"a;b\r\n" + b"1;2\r\n" * 50 + b"3;4",
+            dict(delimiter=b";", has_headers=True),
+            id="crlf-no-trailing-terminator",
+        ),
+        pytest.param(
+            b"a,b\n"
+            + b"".join(b"%d,x\n" % i for i in range(50))
+            + b'50,"'
+            + b"x,y\n" * 40
+            + b'"\n'
+            + b"".join(b"%d,x\n" % i for i in range(51, 100)),
+            dict(has_headers=True),
+            id="quoted-lines",
+        ),
+    ],
+)
+@pytest.mark.parametrize("threads", [1, 3, 8], ids=repr)
+def test_read_parallel(csv_content, options, threads):
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        expected = list(CSVReader(fd.name, with_position=True, **options))
+        reader = rustcsv.read_parallel(
+            fd.name, threads=threads, with_position=True, **options
+        )
+        assert list(reader) == expected
+
+
+def test_read_parallel_unequal_lengths():
+    csv_content = b"a,b\n" * 50 + b"a\n" * 50
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        with pytest.raises(rustcsv.error.UnequalLengthsError) as exc_info:
+            list(rustcsv.read_parallel(fd.name, threads=4))
+    assert exc_info.value.position == rustcsv.error.Position(
+        byte=200, line=51, record=50
+    )
+
+
+def test_read_parallel_unequal_lengths_at_chunk_start():
+    # With two threads, the second chunk starts at the first short record.
+    csv_content = b"a,b\n" * 50 + b"a\n" * 99
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        reader = rustcsv.read_parallel(fd.name, threads=2)
+        with pytest.raises(rustcsv.error.UnequalLengthsError) as exc_info:
+            list(reader)
+    assert exc_info.value.position == rustcsv.error.Position(
+        byte=200, line=51, record=50
+    )
+
+
+def test_read_parallel_unsupported():
+    with pytest.raises(ValueError):
+        rustcsv.read_parallel(io.BytesIO(b"a\n"), threads=2)
+
+
+def test_read_arrow_threads(pyarrow):
+    csv_content = b"a,b\n" + b"".join(b"%d,x%d\n" % (i, i) for i in range(100))
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        schema = pyarrow.schema(
+            [("a", pyarrow.int64()), ("b", pyarrow.string())]
+        )
+        table = rustcsv.read_arrow(fd.name, schema=schema, threads=4)
+    assert table.column("a").to_pylist() == list(range(100))
+    assert table.column("b").to_pylist() == ["x%d" % i for i in range(100)]
+
+
+def test_read_arrow_threads_file_object(pyarrow):
+    for source in [io.BytesIO(b"a\n1\n"), NonSeekable(b"a\n1\n")]:
+        with pytest.raises(ValueError, match="path"):
+            rustcsv.read_arrow(source, threads=2)
+
+
+def _zstd_compress(data: bytes) -> bytes:
+    zstandard = pytest.importorskip("zstandard")
+    return zstandard.ZstdCompressor().compress(data)
+
+
+COMPRESSORS = {
+    "gzip": (gzip.compress, ".gz"),
+    "bz2": (bz2.compress, ".bz2"),
+    "xz": (lzma.compress, ".xz"),
+    "zstd": (_zstd_compress, ".zst"),
+}
+
+
+class NonSeekable(io.RawIOBase):
+    def __init__(self, data: bytes):
+        self._data = io.BytesIO(data)
+
+    def readable(self):
+        return True
+
+    def readinto(self, b):
+        return self._data.readinto(b)
+
+
+@pytest.mark.parametrize("compression", sorted(COMPRESSORS), ids=repr)
+@pytest.mark.parametrize(
+    "source_type, compression_arg",
+    [
+        ("path-with-extension", None),
+        ("path", None),
+        ("path", "compression"),
+        ("BytesIO", None),
+        ("non-seekable", None),
+    ],
+    ids=repr,
+)
+def test_reader_compression(compression, source_type, compression_arg):
+    compress, extension = COMPRESSORS[compression]
+    data = compress(b"a,b\n" + b"1,2\n" * 1000)
+    options = {}
+    if compression_arg is not None:
+        options["compression"] = compression
+
+    with tempfile.NamedTemporaryFile(
+        "wb", suffix=extension if source_type == "path-with-extension" else ""
+    ) as fd:
+        fd.write(data)
+        fd.flush()
+        if source_type.startswith("path"):
+            source = fd.name
+        elif source_type == "BytesIO":
+            source = io.BytesIO(data)
+        else:
+            source = NonSeekable(data)
+        rows = list(CSVReader(source, has_headers=True, **options))
+    assert rows == [{"a": "1", "b": "2"}] * 1000
+
+
+def test_reader_compression_none():
+    with tempfile.NamedTemporaryFile("wb", suffix=".gz") as fd:
+        fd.write(b"a\n")
+        fd.flush()
+        assert list(CSVReader(fd.name, compression="none")) == [("a",)]
+
+
+@pytest.mark.parametrize("source_type", ["path", "BytesIO"], ids=repr)
+def test_reader_compression_infer_bzh(source_type):
+    csv_content = b"BZh9,x\n" b"1,2\n"
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        source = fd.name if source_type == "path" else io.BytesIO(csv_content)
+        rows = list(CSVReader(source))
+    assert rows == [("BZh9", "x"), ("1", "2")]
+
+
+def test_reader_compression_invalid():
+    with pytest.raises(ValueError):
+        CSVReader(io.BytesIO(b""), compression="lz4")
+
+
+def test_reader_compression_seek_unsupported():
+    reader = CSVReader(io.BytesIO(gzip.compress(b"a\nb\n")))
+    assert list(reader) == [("a",), ("b",)]
+    with pytest.raises(ValueError):
+        reader.seek(rustcsv.error.Position(byte=0, line=1, record=0))
+
+
+class SeekFails(NonSeekable):
+    def seekable(self):
+        return True
+
+    def seek(self, offset, whence=io.SEEK_SET):
+        raise OSError("Illegal seek")
+
+
+@pytest.mark.parametrize("source_class", [NonSeekable, SeekFails], ids=repr)
+def test_reader_seek_non_seekable(source_class):
+    reader = CSVReader(source_class(b"a\nb\n"), compression="none")
+    assert list(reader) == [("a",), ("b",)]
+    with pytest.raises(ValueError):
+        reader.seek(rustcsv.error.Position(byte=0, line=1, record=0))
+
+
+@pytest.mark.parametrize("source_class", [NonSeekable, SeekFails], ids=repr)
+@pytest.mark.parametrize("compression", [None, "none"], ids=repr)
+def test_reader_compression_non_seekable(source_class, compression):
+    data = b"a,b\n" b"1,2\n"
+    reader = CSVReader(source_class(data), compression=compression)
+    assert list(reader) == [("a", "b"), ("1", "2")]
+
+
+def test_read_arrow_compression(pyarrow):
+    data = gzip.compress(b"a,b\n" b"1,x\n")
+    table = rustcsv.read_arrow(io.BytesIO(data))
+    assert table.to_pydict() == {"a": ["1"], "b": ["x"]}
+
+
+DECOMPRESSORS = {
+    "gzip": gzip.decompress,
+    "bz2": bz2.decompress,
+    "xz": lzma.decompress,
+}
+
+
+@pytest.mark.parametrize("level", [None, 1], ids=repr)
+@pytest.mark.parametrize("compression", sorted(DECOMPRESSORS), ids=repr)
+def test_writer_compression(compression, level):
+    fd = io.BytesIO()
+    writer = CSVWriter(fd, compression=compression, compression_level=level)
+    for i in range(1000):
+        writer.writerow(("a", str(i)))
+    writer.close()
+    expected = b"".join(b"a,%d\n" % i for i in range(1000))
+    assert DECOMPRESSORS[compression](fd.getvalue()) == expected
+    writer.writerow(("b", "1"))
+    with pytest.raises(IOError):
+        writer.flush()
+
+
+def test_writer_compression_zstd():
+    zstandard = pytest.importorskip("zstandard")
+    fd = io.BytesIO()
+    writer = CSVWriter(fd, compression="zstd", compression_level=19)
+    writer.writerow(("a", "b"))
+    writer.close()
+    decompressed = zstandard.ZstdDecompressor().decompressobj().decompress(
+        fd.getvalue()
+    )
+    assert decompressed == b"a,b\n"
+
+
+def test_writer_compression_roundtrip():
+    with tempfile.NamedTemporaryFile("wb", suffix=".csv.gz") as fd:
+        writer = CSVDictWriter(fd, fieldnames=["a", "b"], compression="gzip")
+        writer.writeheader()
+        writer.writerow({"a": "1", "b": "2"})
+        writer.flush()
+        writer.close()
+        fd.flush()
+        assert list(CSVReader(fd.name, has_headers=True)) == [
+            {"a": "1", "b": "2"}
+        ]
+
+
+def test_writer_compression_finished_on_drop():
+    fd = io.BytesIO()
+    writer = CSVWriter(fd, compression="gzip")
+    writer.writerow(("a",))
+    del writer
+    assert gzip.decompress(fd.getvalue()) == b"a\n"
+
+
+@pytest.mark.parametrize(
+    "writer_class, row",
+    [
+        (CSVWriter, ("a",)),
+        (functools.partial(CSVDictWriter, fieldnames=["a"]), {"a": "a"}),
+    ],
+    ids=["CSVWriter", "CSVDictWriter"],
+)
+def test_writer_context_manager(writer_class, row):
+    fd = io.BytesIO()
+    with writer_class(fd, compression="gzip") as writer:
+        writer.writerow(row)
+    assert gzip.decompress(fd.getvalue()) == b"a\n"
+
+
+@pytest.mark.parametrize(
+    "writer_class, row",
+    [
+        (CSVWriter, ("a",)),
+        (functools.partial(CSVDictWriter, fieldnames=["a"]), {"a": "a"}),
+    ],
+    ids=["CSVWriter", "CSVDictWriter"],
+)
+@pytest.mark.parametrize("compression", ["none", "gzip"], ids=repr)
+def test_writer_closed(writer_class, row, compression):
+    fd = io.BytesIO()
+    writer = writer_class(fd, compression=compression)
+    writer.writerow(row)
+    writer.close()
+    with pytest.raises(ValueError):
+        writer.writerow(row)
+    with pytest.raises(ValueError):
+        writer.flush()
+    writer.close()
+    data = fd.getvalue()
+    if compression == "gzip":
+        data = gzip.decompress(data)
+    assert data == b"a\n"
+
+
+def test_dict_writer_closed_writeheader():
+    writer = CSVDictWriter(io.BytesIO(), fieldnames=["a"])
+    writer.close()
+    with pytest.raises(ValueError):
+        writer.writeheader()
+
+
+@pytest.mark.parametrize(
+    "kwargs",
+    [
+        dict(compression="lz4"),
+        dict(compression="infer"),
+        dict(compression_level=1),
+        dict(compression="gzip", compression_level=10),
+        dict(compression="bz2", compression_level=0),
+    ],
+    ids=repr,
+)
+def test_writer_compression_invalid_args(kwargs):
+    with pytest.raises(ValueError):
+        CSVWriter(io.BytesIO(), **kwargs)
+
+
+def memoryviews_to_bytes(record):
+    def convert(field):
+        return bytes(field) if isinstance(field, memoryview) else field
+
+    if isinstance(record, dict):
+        return {k: convert(v) for k, v in record.items()}
+    return tuple(convert(f) for f in record)
+
+
+@pytest.mark.parametrize(
+    "csv_content, kwargs",
+    [
+        (b"a,b\n1,2\n3,4\n", dict()),
+        (b'a,b\n"x,\ny",2\n"",""\n', dict(has_headers=True)),
+        (b"a;b\r\n1;2\r\n", dict(delimiter=b";", terminator=b"\n")),
+        (b"a,b\n1,2\n", dict(na_values=["1"], quoted_na=False)),
+        (b"a,b\n1,2\n", dict(threaded=True)),
+        (b" a,b\n1,2\n", dict(has_headers=True, trim="headers")),
+        (b"", dict()),
+    ],
+    ids=repr,
+)
+@pytest.mark.parametrize("bytes_mode", [False, True])
+def test_reader_mmap(csv_content, kwargs, bytes_mode):
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        expected = list(CSVReader(fd.name, bytes_mode=bytes_mode, **kwargs))
+        reader = CSVReader(fd.name, bytes_mode=bytes_mode, mmap=True, **kwargs)
+        assert [memoryviews_to_bytes(r) for r in reader] == expected
+
+
+def test_reader_mmap_zero_copy():
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(b'a,b,c\n1,"2",3\n')
+        fd.flush()
+        records = list(CSVReader(fd.name, bytes_mode=True, mmap=True))
+    assert all(isinstance(f, memoryview) for r in records for f in r)
+    assert [[bytes(f) for f in r] for r in records] == [
+        [b"a", b"b", b"c"],
+        [b"1", b"2", b"3"],
+    ]
+    assert all(isinstance(f.obj, mmap.mmap) for f in records[0])
+    # The quoted field, and the field after it, are copies.
+    assert [isinstance(f.obj, mmap.mmap) for f in records[1]] == [
+        True,
+        False,
+        False,
+    ]
+
+
+def test_reader_mmap_dict():
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(b"a,b\n1,\n")
+        fd.flush()
+        reader = CSVReader(
+            fd.name,
+            has_headers=True,
+            bytes_mode=True,
+            na_values=[""],
+            mmap=True,
+        )
+        records = [memoryviews_to_bytes(r) for r in reader]
+    assert records == [{"a": b"1", "b": None}]
+
+
+def test_reader_mmap_seek():
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(b"a,b\n1,2\n3,4\n")
+        fd.flush()
+        reader = CSVReader(fd.name, has_headers=True, mmap=True)
+        next(reader)
+        position = reader.position
+        assert list(reader) == [{"a": "3", "b": "4"}]
+        reader.seek(position)
+        assert list(reader) == [{"a": "1", "b": "2"}, {"a": "3", "b": "4"}]
+
+
+@pytest.mark.parametrize(
+    "source, kwargs",
+    [
+        (io.BytesIO(b"a\n"), dict()),
+        (__file__, dict(threads=2)),
+        (__file__, dict(compression="gzip")),
+        (__file__, dict(encoding="latin-1")),
+        (__file__, dict(skip_lines=1)),
+        (__file__, dict(trim="fields")),
+    ],
+    ids=repr,
+)
+def test_reader_mmap_unsupported(source, kwargs):
+    with pytest.raises(ValueError):
+        CSVReader(source, mmap=True, **kwargs)
+
+
+def test_reader_mmap_compressed():
+    with tempfile.NamedTemporaryFile("wb", suffix=".csv.gz") as fd:
+        fd.write(gzip.compress(b"a,b\n"))
+        fd.flush()
+        with pytest.raises(ValueError):
+            CSVReader(fd.name, mmap=True)
+
+
+def test_reader_crlf_terminator():
+    reader = CSVReader(io.BytesIO(b"a,b\r\n1,2\n3,4\r"), terminator=b"\r\n")
+    assert list(reader) == [("a", "b"), ("1", "2"), ("3", "4")]
+
+
+def test_writer_crlf_terminator():
+    fd = io.BytesIO()
+    writer = CSVWriter(fd, terminator=b"\r\n")
+    writer.writerow(("a", "b"))
+    writer.flush()
+    assert fd.getvalue() == b"a,b\r\n"
+
+
+@pytest.mark.parametrize(
+    "csv_content, expected",
+    [
+        (
+            b"a,b,c\n1,2,3\n4,5,6\n",
+            dict(delimiter=b",", has_headers=True, columns=3),
+        ),
+        (
+            b"name;price\r\nfoo;1,5\r\nbar;2,25\r\n",
+            dict(
+                delimiter=b";",
+                terminator=b"\r\n",
+                has_headers=True,
+                columns=2,
+            ),
+        ),
+        (
+            b'id\tname\n1\t"x\ty"\n2\tz\n',
+            dict(delimiter=b"\t", has_headers=True, columns=2),
+        ),
+        (
+            b"'a'|'b'\n'x|y'|'z'\n",
+            dict(delimiter=b"|", quotechar=b"'", columns=2),
+        ),
+        (
+            b'a,b\n"x \\"y\\" z",1\n',
+            dict(escapechar=b"\\", doublequote=False, columns=2),
+        ),
+        (b"1,2\n3,4\n", dict(has_headers=False, columns=2)),
+        (b"time,val\n12:30,1\n13:45,2\n", dict(delimiter=b",")),
+    ],
+    ids=repr,
+)
+def test_sniff(csv_content, expected):
+    dialect = rustcsv.sniff(io.BytesIO(csv_content))
+    assert {k: getattr(dialect, k) for k in expected} == expected
+
+
+def test_sniff_roundtrip():
+    csv_content = b'id;name\r\n1;"a;b"\r\n2;"c ""d"""\r\n'
+    with tempfile.NamedTemporaryFile("wb", suffix=".csv.gz") as fd:
+        fd.write(gzip.compress(csv_content))
+        fd.flush()
+        dialect = rustcsv.sniff(fd.name)
+        records = list(CSVReader(fd.name, **dialect.reader_options()))
+    assert records == [
+        {"id": "1", "name": "a;b"},
+        {"id": "2", "name": 'c "d"'},
+    ]
+
+    out = io.BytesIO()
+    writer = CSVWriter(out, **dialect.writer_options())
+    writer.writerow(("id", "name"))
+    for record in records:
+        writer.writerow((record["id"], record["name"]))
+    writer.flush()
+    assert out.getvalue() == csv_content
+
+
+def test_sniff_options():
+    fd = io.BytesIO(b"skip\na:b\n1:2\n" + b"3:4\n" * 100)
+    fd.readline()
+    dialect = rustcsv.sniff(fd, sample_size=20, delimiters=b":")
+    assert dialect.delimiter == b":"
+    assert fd.tell() == 5
+
+
+@pytest.mark.parametrize("buffering", [-1, 0], ids=repr)
+def test_sniff_buffered_file(buffering):
+    csv_content = b"skip\n" b"a;b\n" + b"1;2\n" * 100
+    with tempfile.NamedTemporaryFile("wb") as fd:
+        fd.write(csv_content)
+        fd.flush()
+        with open(fd.name, "rb", buffering=buffering) as f:
+            assert f.readline() == b"skip\n"
+            dialect = rustcsv.sniff(f, sample_size=20)
+            assert dialect.delimiter == b";"
+            assert f.readline() == b"a;b\n"
+            assert f.read() == b"1;2\n" * 100
+
+
+@pytest.mark.parametrize(
+    "kwargs",
+    [
+        dict(path_or_fd=io.BytesIO(b"")),
+        dict(path_or_fd=io.BytesIO(b"a\n"), sample_size=0),
+        dict(path_or_fd=io.BytesIO(b"a\n"), delimiters=b""),
+    ],
+    ids=repr,
+)
+def test_sniff_invalid(kwargs):
+    with pytest.raises(ValueError):
+        rustcsv.sniff(**kwargs)
+
+
+def test_dialect_presets():
+    assert set(rustcsv.list_dialects()) >= {
+        "excel",
+        "excel_tab",
+        "unix",
+        "rfc4180",
+    }
+    excel_tab = rustcsv.get_dialect("excel_tab")
+    assert (excel_tab.delimiter, excel_tab.terminator) == (b"\t", b"\r\n")
+    assert rustcsv.get_dialect("unix").quote_style == "always"
+    assert rustcsv.get_dialect("rfc4180") is rustcsv.get_dialect("excel")
+
+
+def test_dialect_options():
+    dialect = rustcsv.Dialect("excel", delimiter=b";", escapechar=b"\\")
+    assert dialect.delimiter == b";"
+    assert dialect.terminator == b"\r\n"
+    assert dialect.quotechar == b'"'
+    assert dialect.escapechar == b"\\"
+    assert dialect.doublequote is True
+    assert dialect.quote_style == "necessary"
+    assert dialect.comment is None
+
+
+def test_dialect_reset():
+    base = rustcsv.Dialect(delimiter=b";", escapechar=b"\\", comment=b"#")
+    dialect = rustcsv.Dialect(base, delimiter=None)
+    assert dialect.delimiter == b";"
+    assert dialect.escapechar == b"\\"
+    assert dialect.comment == b"#"
+
+    dialect = rustcsv.Dialect(base, escapechar=None, comment=None)
+    assert dialect.delimiter == b";"
+    assert dialect.escapechar is None
+    assert dialect.comment is None
+
+    with pytest.raises(TypeError):
+        rustcsv.Dialect(base, separator=b";")
+
+
+class SemicolonDialect(csv.Dialect):
+    delimiter = ";"
+    quotechar = "'"
+    escapechar = None
+    doublequote = True
+    skipinitialspace = False
+    lineterminator = "\n"
+    quoting = csv.QUOTE_MINIMAL
+
+
+@pytest.mark.parametrize(
+    "dialect",
+    [
+        rustcsv.Dialect(delimiter=b";", quotechar=b"'"),
+        SemicolonDialect,
+        SemicolonDialect(),
+        "test_semicolon",
+    ],
+    ids=repr,
+)
+def test_reader_dialect_argument(dialect):
+    rustcsv.register_dialect("test_semicolon", SemicolonDialect)
+    reader = CSVReader(
+        io.BytesIO(b"a;b\n'1;2';3\n"), dialect=dialect, has_headers=True
+    )
+    assert list(reader) == [{"a": "1;2", "b": "3"}]
+
+
+def test_reader_dialect_override():
+    reader = CSVReader(
+        io.BytesIO(b"a\tb,c\n"), dialect=csv.excel_tab, delimiter=b","
+    )
+    assert list(reader) == [("a\tb", "c")]
+
+
+def test_reader_dialect_quote_style_never():
+    reader = CSVReader(
+        io.BytesIO(b'"a",b\n'), dialect=rustcsv.Dialect(quote_style="never")
+    )
+    assert list(reader) == [('"a"', "b")]
+
+
+@pytest.mark.parametrize(
+    "dialect, kwargs, expected",
+    [
+        ("unix", dict(), b'"a","b c"\n'),
+        (csv.excel, dict(), b"a,b c\r\n"),
+        ("excel_tab", dict(quote_style="always"), b'"a"\t"b c"\r\n'),
+        (rustcsv.Dialect(delimiter=b" "), dict(), b'a "b c"\n'),
+    ],
+    ids=repr,
+)
+def test_writer_dialect_argument(dialect, kwargs, expected):
+    fd = io.BytesIO()
+    writer = CSVWriter(fd, dialect=dialect, **kwargs)
+    writer.writerow(("a", "b c"))
+    writer.flush()
+    assert fd.getvalue() == expected
+
+    fd = io.BytesIO()
+    writer = CSVDictWriter(fd, ["x", "y"], dialect=dialect, **kwargs)
+    writer.writerow({"x": "a", "y": "b c"})
+    writer.flush()
+    assert fd.getvalue() == expected
+
+
+def test_sniffed_dialect():
+    dialect = rustcsv.sniff(io.BytesIO(b"a|b\r\n1|2\r\n3|4\r\n")).dialect()
+    assert (dialect.delimiter, dialect.terminator) == (b"|", b"\r\n")
+
+
+class SkipInitialSpaceDialect(csv.excel):
+    skipinitialspace = True
+
+
+@pytest.mark.parametrize(
+    "kwargs, error",
+    [
+        (dict(dialect="unknown"), KeyError),
+        (dict(dialect=SkipInitialSpaceDialect), ValueError),
+        (dict(quote_style="sometimes"), ValueError),
+        (dict(delimiter=b";;"), ValueError),
+    ],
+    ids=repr,
+)
+def test_dialect_invalid(kwargs, error):
+    with pytest.raises(error):
+        rustcsv.Dialect(**kwargs)
+    with pytest.raises(error):
+        CSVWriter(io.BytesIO(), **kwargs)
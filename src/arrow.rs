@@ -0,0 +1,672 @@
+//! Reading CSV into Apache Arrow record batches, which are handed to
+//! `pyarrow` through the [Arrow C data interface].
+//!
+//!  [Arrow C data interface]: https://arrow.apache.org/docs/format/CDataInterface.html
+extern crate csv;
+extern crate pyo3;
+
+use compression::Compression;
+use convert::{conversion_error, parse_bool, ConversionError, DType};
+use missing::ScanDialect;
+use parallel::ParallelRecords;
+use pyo3::exceptions as exc;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use pyo3::types::PyObjectRef;
+use pyo3::PyObject;
+use pyo3::PyResult;
+use pyo3::Python;
+use reader::{
+    csv_error_to_pyerr, make_optional_error_position, make_records_iterator, CSVSource, UTF8Error,
+    UnequalLengthsError,
+};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use std::str;
+use util::get_optional_single_byte;
+
+/// Default number of records per record batch.
+const DEFAULT_BATCH_SIZE: usize = 65536;
+
+/// `ARROW_FLAG_NULLABLE`
+const FLAG_NULLABLE: i64 = 2;
+
+/// `struct ArrowSchema` of the C data interface.
+#[repr(C)]
+pub struct ArrowSchema {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut ArrowSchema,
+    dictionary: *mut ArrowSchema,
+    release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    private_data: *mut c_void,
+}
+
+/// Owns the data an [ArrowSchema] points to.
+struct SchemaPrivate {
+    format: CString,
+    name: CString,
+    children: Vec<*mut ArrowSchema>,
+}
+
+impl ArrowSchema {
+    fn new(format: &str, name: &str, flags: i64, children: Vec<ArrowSchema>) -> ArrowSchema {
+        let mut private = Box::new(SchemaPrivate {
+            format: CString::new(format).unwrap(),
+            name: CString::new(name.replace('\0', "")).unwrap(),
+            children: children
+                .into_iter()
+                .map(|child| Box::into_raw(Box::new(child)))
+                .collect(),
+        });
+        ArrowSchema {
+            format: private.format.as_ptr(),
+            name: private.name.as_ptr(),
+            metadata: ptr::null(),
+            flags,
+            n_children: private.children.len() as i64,
+            children: private.children.as_mut_ptr(),
+            dictionary: ptr::null_mut(),
+            release: Some(release_schema),
+            private_data: Box::into_raw(private) as *mut c_void,
+        }
+    }
+}
+
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    let schema = &mut *schema;
+    let private = Box::from_raw(schema.private_data as *mut SchemaPrivate);
+    for &child in private.children.iter() {
+        drop(Box::from_raw(child));
+    }
+    schema.release = None;
+}
+
+impl Drop for ArrowSchema {
+    /// Releases the schema, unless it has been moved to `pyarrow`.
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self) }
+        }
+    }
+}
+
+/// `struct ArrowArray` of the C data interface.
+#[repr(C)]
+pub struct ArrowArray {
+    length: i64,
+    null_count: i64,
+    offset: i64,
+    n_buffers: i64,
+    n_children: i64,
+    buffers: *mut *const c_void,
+    children: *mut *mut ArrowArray,
+    dictionary: *mut ArrowArray,
+    release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    private_data: *mut c_void,
+}
+
+/// A buffer of an [ArrowArray].
+enum Buffer {
+    /// Validity bitmap of an array without nulls.
+    Absent,
+    U8(Vec<u8>),
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+    F64(Vec<f64>),
+}
+
+impl Buffer {
+    fn as_ptr(&self) -> *const c_void {
+        match *self {
+            Buffer::Absent => ptr::null(),
+            Buffer::U8(ref b) => b.as_ptr() as *const c_void,
+            Buffer::I32(ref b) => b.as_ptr() as *const c_void,
+            Buffer::I64(ref b) => b.as_ptr() as *const c_void,
+            Buffer::F64(ref b) => b.as_ptr() as *const c_void,
+        }
+    }
+}
+
+/// Owns the data an [ArrowArray] points to.
+struct ArrayPrivate {
+    _buffers: Vec<Buffer>,
+    buffer_ptrs: Vec<*const c_void>,
+    children: Vec<*mut ArrowArray>,
+}
+
+impl ArrowArray {
+    fn new(
+        length: usize,
+        null_count: usize,
+        buffers: Vec<Buffer>,
+        children: Vec<ArrowArray>,
+    ) -> ArrowArray {
+        let mut private = Box::new(ArrayPrivate {
+            buffer_ptrs: buffers.iter().map(Buffer::as_ptr).collect(),
+            _buffers: buffers,
+            children: children
+                .into_iter()
+                .map(|child| Box::into_raw(Box::new(child)))
+                .collect(),
+        });
+        ArrowArray {
+            length: length as i64,
+            null_count: null_count as i64,
+            offset: 0,
+            n_buffers: private.buffer_ptrs.len() as i64,
+            n_children: private.children.len() as i64,
+            buffers: private.buffer_ptrs.as_mut_ptr(),
+            children: private.children.as_mut_ptr(),
+            dictionary: ptr::null_mut(),
+            release: Some(release_array),
+            private_data: Box::into_raw(private) as *mut c_void,
+        }
+    }
+}
+
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    let array = &mut *array;
+    let private = Box::from_raw(array.private_data as *mut ArrayPrivate);
+    for &child in private.children.iter() {
+        drop(Box::from_raw(child));
+    }
+    array.release = None;
+}
+
+impl Drop for ArrowArray {
+    /// Releases the array, unless it has been moved to `pyarrow`.
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self) }
+        }
+    }
+}
+
+/// The Arrow types CSV columns can be read as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArrowType {
+    Utf8,
+    Int64,
+    Float64,
+    Bool,
+}
+
+impl ArrowType {
+    /// Parses the `str()` of a `pyarrow.DataType`.
+    fn from_name(name: &str) -> PyResult<ArrowType> {
+        match name {
+            "string" | "utf8" => Ok(ArrowType::Utf8),
+            "int64" => Ok(ArrowType::Int64),
+            "double" | "float64" => Ok(ArrowType::Float64),
+            "bool" => Ok(ArrowType::Bool),
+            _ => Err(exc::ValueError::py_err(format!(
+                "Unsupported Arrow type: {:?}",
+                name
+            ))),
+        }
+    }
+
+    /// The format string of the C data interface.
+    fn format(self) -> &'static str {
+        match self {
+            ArrowType::Utf8 => "u",
+            ArrowType::Int64 => "l",
+            ArrowType::Float64 => "g",
+            ArrowType::Bool => "b",
+        }
+    }
+
+    /// The [DType] reported in a `rustcsv.error.ConversionError`.
+    fn dtype(self) -> DType {
+        match self {
+            ArrowType::Utf8 => DType::Str,
+            ArrowType::Int64 => DType::Int,
+            ArrowType::Float64 => DType::Float,
+            ArrowType::Bool => DType::Bool,
+        }
+    }
+}
+
+/// Bit-packed `bool`s, as used for validity bitmaps and boolean arrays.
+struct Bitmap {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bitmap {
+    fn new() -> Bitmap {
+        Bitmap {
+            bytes: Vec::new(),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bit: bool) {
+        if self.len % 8 == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            self.bytes[self.len / 8] |= 1 << (self.len % 8);
+        }
+        self.len += 1;
+    }
+}
+
+enum ColumnValues {
+    Utf8 { offsets: Vec<i32>, data: Vec<u8> },
+    Int64(Vec<i64>),
+    Float64(Vec<f64>),
+    Bool(Bitmap),
+}
+
+/// Builds the [ArrowArray] of a column, one field at a time.
+struct ColumnBuilder {
+    values: ColumnValues,
+    /// Whether the column may contain nulls.
+    nullable: bool,
+    validity: Bitmap,
+    null_count: usize,
+}
+
+/// Why a field could not be appended to a [ColumnBuilder].
+enum PushError {
+    Utf8(str::Utf8Error),
+    Conversion,
+    /// A null in a column that is not nullable.
+    Null,
+    /// The string data of the batch exceeds the 2 GiB that 32-bit offsets
+    /// can address.
+    Overflow,
+}
+
+impl ColumnBuilder {
+    fn new(arrow_type: ArrowType, nullable: bool) -> ColumnBuilder {
+        ColumnBuilder {
+            values: match arrow_type {
+                ArrowType::Utf8 => ColumnValues::Utf8 {
+                    offsets: vec![0],
+                    data: Vec::new(),
+                },
+                ArrowType::Int64 => ColumnValues::Int64(Vec::new()),
+                ArrowType::Float64 => ColumnValues::Float64(Vec::new()),
+                ArrowType::Bool => ColumnValues::Bool(Bitmap::new()),
+            },
+            nullable,
+            validity: Bitmap::new(),
+            null_count: 0,
+        }
+    }
+
+    /// Appends `field`, empty fields of non-string columns are null.
+    fn push(&mut self, field: &[u8]) -> Result<(), PushError> {
+        let text = str::from_utf8(field).map_err(PushError::Utf8)?;
+        if let ColumnValues::Utf8 {
+            ref mut offsets,
+            ref mut data,
+        } = self.values
+        {
+            data.extend_from_slice(field);
+            if data.len() > i32::max_value() as usize {
+                return Err(PushError::Overflow);
+            }
+            offsets.push(data.len() as i32);
+            self.validity.push(true);
+            return Ok(());
+        }
+
+        let text = text.trim();
+        if text.is_empty() {
+            if !self.nullable {
+                return Err(PushError::Null);
+            }
+            self.push_null();
+            return Ok(());
+        }
+        match self.values {
+            ColumnValues::Int64(ref mut values) => {
+                values.push(text.parse().map_err(|_| PushError::Conversion)?)
+            }
+            ColumnValues::Float64(ref mut values) => {
+                values.push(text.parse().map_err(|_| PushError::Conversion)?)
+            }
+            ColumnValues::Bool(ref mut values) => {
+                values.push(parse_bool(text).ok_or(PushError::Conversion)?)
+            }
+            ColumnValues::Utf8 { .. } => unreachable!(),
+        }
+        self.validity.push(true);
+        Ok(())
+    }
+
+    fn push_null(&mut self) {
+        match self.values {
+            ColumnValues::Utf8 {
+                ref mut offsets,
+                ref data,
+            } => offsets.push(data.len() as i32),
+            ColumnValues::Int64(ref mut values) => values.push(0),
+            ColumnValues::Float64(ref mut values) => values.push(0.0),
+            ColumnValues::Bool(ref mut values) => values.push(false),
+        }
+        self.validity.push(false);
+        self.null_count += 1;
+    }
+
+    fn finish(self) -> ArrowArray {
+        let length = self.validity.len;
+        let validity = if self.null_count == 0 {
+            Buffer::Absent
+        } else {
+            Buffer::U8(self.validity.bytes)
+        };
+        let buffers = match self.values {
+            ColumnValues::Utf8 { offsets, data } => {
+                vec![validity, Buffer::I32(offsets), Buffer::U8(data)]
+            }
+            ColumnValues::Int64(values) => vec![validity, Buffer::I64(values)],
+            ColumnValues::Float64(values) => vec![validity, Buffer::F64(values)],
+            ColumnValues::Bool(values) => vec![validity, Buffer::U8(values.bytes)],
+        };
+        ArrowArray::new(length, self.null_count, buffers, Vec::new())
+    }
+}
+
+/// The columns of the record batches.
+struct Columns {
+    names: Vec<String>,
+    types: Vec<ArrowType>,
+    /// Whether each column may contain nulls.
+    nullable: Vec<bool>,
+}
+
+impl Columns {
+    /// Nullable string columns named `names`.
+    fn strings(names: Vec<String>) -> Columns {
+        let len = names.len();
+        Columns {
+            names,
+            types: vec![ArrowType::Utf8; len],
+            nullable: vec![true; len],
+        }
+    }
+
+    fn builders(&self) -> Vec<ColumnBuilder> {
+        self.types
+            .iter()
+            .zip(self.nullable.iter())
+            .map(|(t, nullable)| ColumnBuilder::new(*t, *nullable))
+            .collect()
+    }
+}
+
+/// Collects records into record batches of `columns`.
+struct BatchBuilder<'a> {
+    columns: &'a Columns,
+    builders: Vec<ColumnBuilder>,
+    length: usize,
+}
+
+impl<'a> BatchBuilder<'a> {
+    fn new(columns: &'a Columns) -> BatchBuilder<'a> {
+        BatchBuilder {
+            columns,
+            builders: columns.builders(),
+            length: 0,
+        }
+    }
+
+    fn push(&mut self, r: &csv::ByteRecord) -> PyResult<()> {
+        if r.len() != self.builders.len() {
+            return Err(UnequalLengthsError::py_err((
+                format!(
+                    "Unequal lengths: Expected length {:?} got length {:?}",
+                    self.builders.len(),
+                    r.len(),
+                ),
+                make_optional_error_position(r.position())?,
+            )));
+        }
+        for (column, (builder, field)) in self.builders.iter_mut().zip(r.iter()).enumerate() {
+            match builder.push(field) {
+                Ok(()) => {}
+                Err(PushError::Utf8(error)) => {
+                    return Err(UTF8Error::py_err((
+                        format!("invalid utf-8 in field {}: {}", column, error),
+                        make_optional_error_position(r.position())?,
+                    )))
+                }
+                Err(PushError::Conversion) => {
+                    return Err(conversion_error(
+                        self.columns.types[column].dtype(),
+                        r.position(),
+                        self.columns.names[column].as_str(),
+                        &String::from_utf8_lossy(field),
+                    )?)
+                }
+                Err(PushError::Null) => {
+                    let name = self.columns.names[column].as_str();
+                    return Err(ConversionError::py_err((
+                        format!("Empty value in non-nullable column {:?}", name),
+                        make_optional_error_position(r.position())?,
+                        name.to_string(),
+                        String::from_utf8_lossy(field).into_owned(),
+                    )));
+                }
+                Err(PushError::Overflow) => {
+                    return Err(exc::ValueError::py_err(
+                        "Too much string data in one record batch, use a smaller batch_size",
+                    ))
+                }
+            }
+        }
+        self.length += 1;
+        Ok(())
+    }
+
+    /// Returns the record batch, as a struct array, and resets the builder.
+    fn finish(&mut self) -> ArrowArray {
+        let builders = ::std::mem::replace(&mut self.builders, self.columns.builders());
+        let length = ::std::mem::replace(&mut self.length, 0);
+        ArrowArray::new(
+            length,
+            0,
+            vec![Buffer::Absent],
+            builders.into_iter().map(ColumnBuilder::finish).collect(),
+        )
+    }
+}
+
+/// Exports the schema of the record batches.
+fn make_schema(columns: &Columns) -> ArrowSchema {
+    let fields = (0..columns.names.len())
+        .map(|i| {
+            let flags = if columns.nullable[i] {
+                FLAG_NULLABLE
+            } else {
+                0
+            };
+            ArrowSchema::new(
+                columns.types[i].format(),
+                &columns.names[i],
+                flags,
+                Vec::new(),
+            )
+        })
+        .collect();
+    ArrowSchema::new("+s", "", 0, fields)
+}
+
+/// Reads the column names, types and nullability from a `pyarrow.Schema`.
+fn schema_from_object(schema: &PyObjectRef) -> PyResult<Columns> {
+    let mut columns = Columns::strings(Vec::new());
+    for field in schema.iter()? {
+        let field = field?;
+        let type_name = field.getattr("type")?.str()?.to_string()?.into_owned();
+        columns.names.push(field.getattr("name")?.extract()?);
+        columns
+            .types
+            .push(ArrowType::from_name(type_name.as_str())?);
+        columns.nullable.push(field.getattr("nullable")?.extract()?);
+    }
+    Ok(columns)
+}
+
+/// read_arrow(path_or_fd, schema=None, batch_size=None, delimiter=None, has_headers=None, threads=None)
+/// --
+/// Reads a CSV into a `pyarrow.Table`.
+///
+/// The records are parsed into Arrow arrays in Rust, which are handed to
+/// `pyarrow` through the Arrow C data interface, without creating Python
+/// objects for the fields.
+///
+/// - `path_or_fd` - Either a string path to a file or a [binary file],
+///   which is decompressed if it is compressed.
+/// - `schema` - `pyarrow.Schema` of the columns, supported types are
+///   `string`, `int64`, `float64` and `bool`. Empty fields of non-string
+///   columns are null, and raise a `rustcsv.error.ConversionError` in
+///   columns that are not nullable. If `None`, all columns are read as
+///   `string`.
+/// - `batch_size` - Number of records per record batch, defaults to 65536.
+/// - `delimiter` - CSV field separator, defaults to `b','`.
+/// - `has_headers` - Whether the first record is the header, defaults to
+///   `True`. The column names of `schema` take precedence, without either
+///   the columns are named `f0`, `f1`, etc.
+/// - `threads` - Number of threads to parse the CSV with, only supported
+///   when `path_or_fd` is a path. Records must be terminated by `\n` or
+///   `\r\n`. Disabled if `None`.
+///
+///  [binary file]: https://docs.python.org/3/glossary.html#term-binary-file
+#[pyfunction]
+pub fn read_arrow(
+    path_or_fd: &PyObjectRef,
+    schema: Option<&PyObjectRef>,
+    batch_size: Option<usize>,
+    delimiter: Option<&PyBytes>,
+    has_headers: Option<bool>,
+    threads: Option<usize>,
+    py: Python,
+) -> PyResult<PyObject> {
+    let has_headers = has_headers.unwrap_or(true);
+    let batch_size = batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    if batch_size == 0 {
+        return Err(exc::ValueError::py_err("batch_size must be at least 1"));
+    }
+    let pyarrow = py.import("pyarrow")?;
+
+    let delimiter = get_optional_single_byte(delimiter, b',')?;
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .delimiter(delimiter)
+        .flexible(true)
+        .has_headers(has_headers);
+    let mut source = CSVSource::from_object(py, path_or_fd.to_object(py))?;
+    let compression = source.detect_compression(Compression::Infer)?;
+    let records = match (threads, source) {
+        (Some(0), _) => return Err(exc::ValueError::py_err("threads must be at least 1")),
+        (Some(_), CSVSource::Readable(_)) | (Some(_), CSVSource::Mapped(_)) => {
+            return Err(exc::ValueError::py_err(
+                "threads is only supported when reading from a path",
+            ))
+        }
+        (Some(_), _) if compression != Compression::None => {
+            return Err(exc::ValueError::py_err(
+                "threads is not supported for compressed files",
+            ))
+        }
+        (Some(threads), CSVSource::Path(path)) => {
+            let dialect = ScanDialect {
+                delimiter,
+                terminator: csv::Terminator::CRLF,
+                quote: b'"',
+                escape: None,
+                double_quote: true,
+                comment: None,
+                quoting: true,
+                has_headers,
+            };
+            ParallelRecords::open(path, builder, dialect, true, threads).map(|(header, records)| {
+                let records: Box<Iterator<Item = csv::Result<csv::ByteRecord>>> = Box::new(records);
+                (header, records)
+            })
+        }
+        (None, source) => make_records_iterator(
+            source,
+            compression,
+            None,
+            &builder,
+            has_headers,
+            None,
+            false,
+            None,
+        ),
+    };
+    let (header, mut iter) = match records {
+        Ok(res) => res,
+        Err(error) => return Err(csv_error_to_pyerr(error)?),
+    };
+
+    // Without a schema or header, the number of columns is only known after
+    // reading the first record.
+    let mut first = None;
+    let columns = match (schema, header) {
+        (Some(schema), _) => schema_from_object(schema)?,
+        (None, Some(header)) => Columns::strings(
+            header
+                .iter()
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .collect(),
+        ),
+        (None, None) => {
+            first = match iter.next() {
+                Some(Ok(r)) => Some(r),
+                Some(Err(error)) => return Err(csv_error_to_pyerr(error)?),
+                None => None,
+            };
+            let len = first.as_ref().map_or(0, |r| r.len());
+            Columns::strings((0..len).map(|i| format!("f{}", i)).collect())
+        }
+    };
+
+    let mut c_schema = Box::new(make_schema(&columns));
+    let table_schema = pyarrow.getattr("Schema")?.call_method1(
+        "_import_from_c",
+        (&mut *c_schema as *mut ArrowSchema as usize,),
+    )?;
+    let record_batch = pyarrow.getattr("RecordBatch")?;
+
+    let mut batches = Vec::new();
+    let mut batch = BatchBuilder::new(&columns);
+    if let Some(r) = first {
+        batch.push(&r)?;
+    }
+    loop {
+        let next = iter.next();
+        if batch.length == batch_size || (next.is_none() && batch.length > 0) {
+            let mut c_array = Box::new(batch.finish());
+            batches.push(record_batch.call_method1(
+                "_import_from_c",
+                (&mut *c_array as *mut ArrowArray as usize, table_schema),
+            )?);
+        }
+        match next {
+            Some(Ok(r)) => batch.push(&r)?,
+            Some(Err(error)) => return Err(csv_error_to_pyerr(error)?),
+            None => break,
+        }
+    }
+
+    Ok(pyarrow
+        .getattr("Table")?
+        .call_method1("from_batches", (batches, table_schema))?
+        .to_object(py))
+}
+
+/// Adds the functions of this module to the `_rustcsv` module.
+pub fn register(m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_function!(read_arrow))
+}
@@ -0,0 +1,240 @@
+//! Decompressing sources and compressing output.
+extern crate bzip2;
+extern crate flate2;
+extern crate pyo3;
+extern crate xz2;
+extern crate zstd;
+
+use pyo3::exceptions as exc;
+use pyo3::PyResult;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+
+/// Length of the longest magic number, of `bzip2` with the magic number of
+/// the block that follows the stream header.
+const MAGIC_LEN: u64 = 10;
+
+/// Whether `magic` starts with a `bzip2` stream header, `BZh` and the block
+/// size, followed by the magic number of a block, or of the end of an empty
+/// stream. `BZh` alone is too likely to start a plain CSV.
+fn is_bzip2_magic(magic: &[u8]) -> bool {
+    magic.len() >= 10
+        && magic.starts_with(b"BZh")
+        && magic[3] >= b'1'
+        && magic[3] <= b'9'
+        && (&magic[4..10] == b"\x31\x41\x59\x26\x53\x59"
+            || &magic[4..10] == b"\x17\x72\x45\x38\x50\x90")
+}
+
+/// The compression of a source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    /// Detected from the magic number at the start of the source.
+    Infer,
+    None,
+    Gzip,
+    Zstd,
+    Bz2,
+    Xz,
+}
+
+impl Compression {
+    /// Parses the `compression` argument.
+    pub fn from_name(name: &str) -> PyResult<Compression> {
+        match name {
+            "infer" => Ok(Compression::Infer),
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            "bz2" | "bzip2" => Ok(Compression::Bz2),
+            "xz" => Ok(Compression::Xz),
+            _ => Err(exc::ValueError::py_err(format!(
+                "Invalid compression: {:?}, expected one of \"infer\", \"none\", \
+                 \"gzip\", \"zstd\", \"bz2\" or \"xz\"",
+                name
+            ))),
+        }
+    }
+
+    /// Detects the compression from the extension of `path`, returning
+    /// [Compression::Infer] for unknown extensions.
+    pub fn from_extension(path: &str) -> Compression {
+        let ext = path.rsplit('.').next().unwrap_or("");
+        match ext {
+            "gz" => Compression::Gzip,
+            "zst" => Compression::Zstd,
+            "bz2" => Compression::Bz2,
+            "xz" => Compression::Xz,
+            _ => Compression::Infer,
+        }
+    }
+
+    /// Detects the compression from the first bytes of a source.
+    fn from_magic(magic: &[u8]) -> Compression {
+        if magic.starts_with(b"\x1f\x8b") {
+            Compression::Gzip
+        } else if magic.starts_with(b"\x28\xb5\x2f\xfd") {
+            Compression::Zstd
+        } else if is_bzip2_magic(magic) {
+            Compression::Bz2
+        } else if magic.starts_with(b"\xfd7zXZ\x00") {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Resolves [Compression::Infer] by reading the magic number of `source`,
+    /// then seeking back to where it started.
+    pub fn detect<R: Read + Seek>(self, source: &mut R) -> io::Result<Compression> {
+        if self != Compression::Infer {
+            return Ok(self);
+        }
+        let mut magic = Vec::new();
+        source.by_ref().take(MAGIC_LEN).read_to_end(&mut magic)?;
+        source.seek(SeekFrom::Current(-(magic.len() as i64)))?;
+        Ok(Compression::from_magic(&magic))
+    }
+
+    /// Wraps `source` in a decoder, [Compression::Infer] reads the magic
+    /// number from `source` first.
+    pub fn decoder(self, mut source: Box<Read + Send>) -> io::Result<Box<Read + Send>> {
+        Ok(match self {
+            Compression::Infer => {
+                let mut magic = Vec::new();
+                source.by_ref().take(MAGIC_LEN).read_to_end(&mut magic)?;
+                let compression = Compression::from_magic(&magic);
+                return compression.decoder(Box::new(io::Cursor::new(magic).chain(source)));
+            }
+            Compression::None => source,
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(source)),
+            Compression::Zstd => Box::new(zstd::stream::Decoder::new(source)?),
+            Compression::Bz2 => Box::new(bzip2::read::MultiBzDecoder::new(source)),
+            Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(source)),
+        })
+    }
+}
+
+/// Compresses the data written to it, the compressed stream is finished by
+/// [Encoder::finish] or when the [Encoder] is dropped.
+pub struct Encoder<W: Write> {
+    state: EncoderState<W>,
+}
+
+enum EncoderState<W: Write> {
+    None(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::stream::Encoder<W>),
+    Bz2(bzip2::write::BzEncoder<W>),
+    Xz(xz2::write::XzEncoder<W>),
+    /// Set once the compressed stream is finished, nothing can be written
+    /// after it.
+    Finished(W),
+    /// Set if finishing the compressed stream failed.
+    Failed,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Compresses the data written to `inner` with `compression`, at
+    /// `level`, which defaults to the default level of each format.
+    pub fn new(inner: W, compression: Compression, level: Option<u32>) -> PyResult<Encoder<W>> {
+        let check_level = |default: u32, min: u32, max: u32| match level {
+            Some(level) if level < min || level > max => Err(exc::ValueError::py_err(format!(
+                "Invalid compression level {} for {:?}, expected {} to {}",
+                level, compression, min, max
+            ))),
+            Some(level) => Ok(level),
+            None => Ok(default),
+        };
+        let state = match compression {
+            Compression::Infer => {
+                return Err(exc::ValueError::py_err(
+                    "compression can't be \"infer\" when writing",
+                ))
+            }
+            Compression::None => {
+                if level.is_some() {
+                    return Err(exc::ValueError::py_err(
+                        "compression_level requires compression",
+                    ));
+                }
+                EncoderState::None(inner)
+            }
+            Compression::Gzip => EncoderState::Gzip(flate2::write::GzEncoder::new(
+                inner,
+                flate2::Compression::new(check_level(6, 0, 9)?),
+            )),
+            Compression::Zstd => EncoderState::Zstd(zstd::stream::Encoder::new(
+                inner,
+                check_level(3, 1, 22)? as i32,
+            )?),
+            Compression::Bz2 => EncoderState::Bz2(bzip2::write::BzEncoder::new(
+                inner,
+                bzip2::Compression::new(check_level(9, 1, 9)?),
+            )),
+            Compression::Xz => {
+                EncoderState::Xz(xz2::write::XzEncoder::new(inner, check_level(6, 0, 9)?))
+            }
+        };
+        Ok(Encoder { state })
+    }
+
+    /// Finishes the compressed stream and flushes the inner writer.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let inner = match mem::replace(&mut self.state, EncoderState::Failed) {
+            EncoderState::None(inner) | EncoderState::Finished(inner) => inner,
+            EncoderState::Gzip(encoder) => encoder.finish()?,
+            EncoderState::Zstd(encoder) => encoder.finish()?,
+            EncoderState::Bz2(encoder) => encoder.finish()?,
+            EncoderState::Xz(encoder) => encoder.finish()?,
+            EncoderState::Failed => return Err(finished_error()),
+        };
+        self.state = EncoderState::Finished(inner);
+        self.flush()
+    }
+}
+
+fn finished_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "The compressed stream is finished")
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.state {
+            EncoderState::None(ref mut inner) => inner.write(buf),
+            EncoderState::Gzip(ref mut encoder) => encoder.write(buf),
+            EncoderState::Zstd(ref mut encoder) => encoder.write(buf),
+            EncoderState::Bz2(ref mut encoder) => encoder.write(buf),
+            EncoderState::Xz(ref mut encoder) => encoder.write(buf),
+            EncoderState::Finished(_) | EncoderState::Failed => Err(finished_error()),
+        }
+    }
+
+    /// Flushes the data compressed so far to the inner writer, without
+    /// finishing the compressed stream.
+    fn flush(&mut self) -> io::Result<()> {
+        match self.state {
+            EncoderState::None(ref mut inner) | EncoderState::Finished(ref mut inner) => {
+                inner.flush()
+            }
+            EncoderState::Gzip(ref mut encoder) => encoder.flush(),
+            EncoderState::Zstd(ref mut encoder) => encoder.flush(),
+            EncoderState::Bz2(ref mut encoder) => encoder.flush(),
+            EncoderState::Xz(ref mut encoder) => encoder.flush(),
+            EncoderState::Failed => Err(finished_error()),
+        }
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        match self.state {
+            EncoderState::None(_) | EncoderState::Finished(_) | EncoderState::Failed => (),
+            _ => {
+                if let Err(error) = self.finish() {
+                    error!("Could not finish the compressed stream: {:?}", error);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+extern crate pyo3;
+
+use pyo3::exceptions as exc;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pyo3::types::PyObjectRef;
+use pyo3::types::PyString;
+use pyo3::PyObject;
+use pyo3::PyResult;
+use pyo3::PyTryFrom;
+use pyo3::Python;
+use reader::make_optional_error_position;
+
+import_exception!(rustcsv.error, ConversionError);
+
+/// The type a CSV field is converted to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DType {
+    Str,
+    Int,
+    Float,
+    Bool,
+    /// `decimal.Decimal`
+    Decimal,
+}
+
+impl DType {
+    /// Parses a dtype from either its name, e.g. `"int"`, or the
+    /// corresponding Python type, e.g. `int` or `decimal.Decimal`.
+    pub fn from_object(py: Python, obj: &PyObjectRef) -> PyResult<DType> {
+        let name: String = if py.is_instance::<PyString, _>(obj)? {
+            obj.extract()?
+        } else {
+            match obj.getattr("__name__") {
+                Ok(name) => name.extract::<String>()?.to_lowercase(),
+                Err(_) => {
+                    return Err(exc::TypeError::py_err(format!(
+                        "Expected a dtype name or type, got {:?}",
+                        obj
+                    )))
+                }
+            }
+        };
+        DType::from_name(name.as_str())
+    }
+
+    pub fn from_name(name: &str) -> PyResult<DType> {
+        match name {
+            "str" => Ok(DType::Str),
+            "int" => Ok(DType::Int),
+            "float" => Ok(DType::Float),
+            "bool" => Ok(DType::Bool),
+            "decimal" => Ok(DType::Decimal),
+            _ => Err(exc::ValueError::py_err(format!(
+                "Invalid dtype: {:?}",
+                name
+            ))),
+        }
+    }
+}
+
+/// Per column [DType]s, indexed by column.
+pub struct ColumnTypes {
+    dtypes: Vec<Option<DType>>,
+    /// `decimal.Decimal`, if any column is of [DType::Decimal].
+    decimal_type: Option<PyObject>,
+}
+
+impl ColumnTypes {
+    /// Creates [ColumnTypes] from a Python `dict` mapping either column
+    /// indices or column names to dtypes.
+    ///
+    /// Column names are looked up in `fieldnames`.
+    pub fn from_object(
+        py: Python,
+        dtypes: &PyObjectRef,
+        fieldnames: Option<&[String]>,
+    ) -> PyResult<ColumnTypes> {
+        let dtypes_dict: &PyDict = match <PyDict as PyTryFrom>::try_from(dtypes) {
+            Ok(d) => d,
+            Err(_) => {
+                return Err(exc::TypeError::py_err(format!(
+                    "Expected dtypes to be a dict, got {:?}",
+                    dtypes
+                )))
+            }
+        };
+
+        let mut by_index: Vec<Option<DType>> = Vec::new();
+        for (key, value) in dtypes_dict.iter() {
+            let index = column_index(py, key, fieldnames)?;
+            if by_index.len() <= index {
+                by_index.resize(index + 1, None);
+            }
+            by_index[index] = Some(DType::from_object(py, value)?);
+        }
+        ColumnTypes::new(py, by_index)
+    }
+
+    /// Creates [ColumnTypes] from [DType]s indexed by column.
+    pub fn new(py: Python, dtypes: Vec<Option<DType>>) -> PyResult<ColumnTypes> {
+        let decimal_type = if dtypes.contains(&Some(DType::Decimal)) {
+            Some(py.import("decimal")?.get("Decimal")?.to_object(py))
+        } else {
+            None
+        };
+        Ok(ColumnTypes {
+            dtypes,
+            decimal_type,
+        })
+    }
+
+    /// The [DType] of `column`, if any.
+    pub fn get(&self, column: usize) -> Option<DType> {
+        self.dtypes.get(column).and_then(|d| *d)
+    }
+
+    /// Converts `field` to a Python object of type `dtype`.
+    ///
+    /// Returns `None` if `field` can not be converted.
+    pub fn convert(&self, py: Python, dtype: DType, field: &str) -> Option<PyObject> {
+        match dtype {
+            DType::Str => Some(field.to_object(py)),
+            DType::Int => {
+                let text = field.trim();
+                match text.parse::<i64>() {
+                    Ok(i) => Some(i.to_object(py)),
+                    // Fall back to Python for integers that don't fit an i64
+                    Err(_) if is_integer(text) => py
+                        .import("builtins")
+                        .and_then(|builtins| builtins.call1("int", (text,)))
+                        .map(|i| i.to_object(py))
+                        .ok(),
+                    Err(_) => None,
+                }
+            }
+            DType::Float => field.trim().parse::<f64>().ok().map(|f| f.to_object(py)),
+            DType::Bool => parse_bool(field.trim()).map(|b| b.to_object(py)),
+            DType::Decimal => self
+                .decimal_type
+                .as_ref()
+                .and_then(|decimal| decimal.call1(py, (field.trim(),)).ok()),
+        }
+    }
+}
+
+/// Resolves a column name or index, e.g. a `dtypes` key, to a column
+/// index.
+pub fn column_index(
+    py: Python,
+    key: &PyObjectRef,
+    fieldnames: Option<&[String]>,
+) -> PyResult<usize> {
+    if py.is_instance::<PyString, _>(key)? {
+        let name: String = key.extract()?;
+        match fieldnames.and_then(|f| f.iter().position(|n| *n == name)) {
+            Some(index) => Ok(index),
+            None => Err(exc::KeyError::py_err(format!(
+                "Unknown column name: {:?}",
+                name
+            ))),
+        }
+    } else {
+        key.extract::<usize>()
+    }
+}
+
+fn is_integer(text: &str) -> bool {
+    let digits = text.trim_start_matches(|c| c == '-' || c == '+');
+    !digits.is_empty()
+        && text.len() - digits.len() <= 1
+        && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Parses a `bool` from `"true"`/`"false"`, `"t"`/`"f"`, `"yes"`/`"no"`,
+/// `"y"`/`"n"` or `"1"`/`"0"`, ignoring case.
+pub fn parse_bool(text: &str) -> Option<bool> {
+    match text.to_lowercase().as_str() {
+        "true" | "t" | "yes" | "y" | "1" => Some(true),
+        "false" | "f" | "no" | "n" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Creates a `rustcsv.error.ConversionError`.
+///
+/// # Arguments
+///
+/// * `dtype` - The [DType] the field could not be converted to.
+/// * `position` - Position of the record containing the field.
+/// * `column` - Column name or index of the field.
+/// * `value` - The field.
+pub fn conversion_error<C: ToPyObject>(
+    dtype: DType,
+    position: Option<&csv::Position>,
+    column: C,
+    value: &str,
+) -> PyResult<PyErr> {
+    let position = make_optional_error_position(position)?;
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    Ok(ConversionError::py_err((
+        format!("Could not convert {:?} to {:?}", value, dtype),
+        position,
+        column.to_object(py),
+        value,
+    )))
+}
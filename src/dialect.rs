@@ -0,0 +1,334 @@
+//! CSV dialects shared by [CSVReader](::reader::CSVReader) and the writers.
+//!
+//! The registry of named dialects, and the built-in presets, are in the
+//! `rustcsv.dialect` Python module.
+extern crate csv;
+extern crate pyo3;
+
+use pyo3::exceptions as exc;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyObjectRef, PyString};
+use pyo3::PyObject;
+use pyo3::PyRawObject;
+use pyo3::PyResult;
+use pyo3::Python;
+use util::{get_single_byte, get_single_byte_option, get_terminator, terminator_bytes};
+
+/// Values of the stdlib's `csv.QUOTE_*` constants, and the corresponding
+/// quote styles.
+const STDLIB_QUOTING: [(i64, &str); 4] = [
+    (0, "necessary"),
+    (1, "always"),
+    (2, "non_numeric"),
+    (3, "never"),
+];
+
+/// Parses the name of a [csv::QuoteStyle].
+pub fn parse_quote_style(quote_style: &str) -> PyResult<csv::QuoteStyle> {
+    match quote_style {
+        "necessary" => Ok(csv::QuoteStyle::Necessary),
+        "always" => Ok(csv::QuoteStyle::Always),
+        "never" => Ok(csv::QuoteStyle::Never),
+        "non_numeric" => Ok(csv::QuoteStyle::NonNumeric),
+        _ => Err(exc::ValueError::py_err(format!(
+            "Invalid quote style: {:?}",
+            quote_style
+        ))),
+    }
+}
+
+/// Extracts a single byte from either `bytes` or an ASCII `str`, as used by
+/// the stdlib's `csv.Dialect`.
+fn get_single_char(value: &PyObjectRef) -> PyResult<u8> {
+    if let Ok(s) = value.extract::<String>() {
+        if s.len() == 1 {
+            return Ok(s.as_bytes()[0]);
+        }
+        return Err(exc::ValueError::py_err(format!(
+            "Expected a single ASCII character, got {:?}",
+            s
+        )));
+    }
+    get_single_byte(value.extract()?)
+}
+
+/// Like [get_single_char], but `None` is returned as `None`.
+fn get_single_char_option(value: &PyObjectRef) -> PyResult<Option<u8>> {
+    if value.is_none() {
+        Ok(None)
+    } else {
+        Ok(Some(get_single_char(value)?))
+    }
+}
+
+// Python docstring for Dialect
+/// Dialect(dialect, *, delimiter, terminator, quotechar, escapechar, doublequote, quote_style, comment)
+/// --
+#[pyclass]
+#[derive(Clone)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub terminator: csv::Terminator,
+    pub quote: u8,
+    pub escape: Option<u8>,
+    pub double_quote: bool,
+    /// Name of the quote style, see [parse_quote_style]. Only used when
+    /// writing, except that `"never"` disables quoting when reading.
+    pub quote_style: String,
+    pub comment: Option<u8>,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            delimiter: b',',
+            terminator: csv::Terminator::Any(b'\n'),
+            quote: b'"',
+            escape: None,
+            double_quote: true,
+            quote_style: "necessary".into(),
+            comment: None,
+        }
+    }
+}
+
+impl Dialect {
+    /// Resolves the `dialect` argument of [CSVReader](::reader::CSVReader)
+    /// and the writers, either a [Dialect], the name of a registered
+    /// dialect, or a stdlib `csv.Dialect`. Defaults to [Dialect::default].
+    pub fn from_object(py: Python, dialect: Option<&PyObjectRef>) -> PyResult<Dialect> {
+        let dialect = match dialect {
+            Some(dialect) if !dialect.is_none() => dialect,
+            _ => return Ok(Dialect::default()),
+        };
+        if let Ok(dialect) = dialect.extract::<&Dialect>() {
+            return Ok(dialect.clone());
+        }
+        if py.is_instance::<PyString, _>(dialect)? {
+            let registered = py
+                .import("rustcsv.dialect")?
+                .call1("get_dialect", (dialect,))?;
+            return Ok(registered.extract::<&Dialect>()?.clone());
+        }
+        Dialect::from_csv_dialect(dialect)
+    }
+
+    /// Converts a subclass, or an instance, of the stdlib's `csv.Dialect`.
+    fn from_csv_dialect(dialect: &PyObjectRef) -> PyResult<Dialect> {
+        if dialect.getattr("skipinitialspace")?.is_true()? {
+            return Err(exc::ValueError::py_err("skipinitialspace is not supported"));
+        }
+        let quoting: i64 = dialect.getattr("quoting")?.extract()?;
+        let quote_style = match STDLIB_QUOTING.iter().find(|&&(q, _)| q == quoting) {
+            Some(&(_, quote_style)) => quote_style,
+            None => {
+                return Err(exc::ValueError::py_err(format!(
+                    "Unsupported quoting: {:?}",
+                    quoting
+                )))
+            }
+        };
+        let lineterminator = dialect.getattr("lineterminator")?;
+        let terminator = if lineterminator.extract::<String>().ok() == Some("\r\n".into()) {
+            csv::Terminator::CRLF
+        } else {
+            csv::Terminator::Any(get_single_char(lineterminator)?)
+        };
+        Ok(Dialect {
+            delimiter: get_single_char(dialect.getattr("delimiter")?)?,
+            terminator,
+            quote: get_single_char_option(dialect.getattr("quotechar")?)?.unwrap_or(b'"'),
+            escape: get_single_char_option(dialect.getattr("escapechar")?)?,
+            double_quote: dialect.getattr("doublequote")?.is_true()?,
+            quote_style: quote_style.into(),
+            comment: None,
+        })
+    }
+
+    /// Overrides the options that are set, as passed to
+    /// [CSVReader](::reader::CSVReader) or the writers.
+    pub fn update(
+        &mut self,
+        delimiter: Option<&PyBytes>,
+        terminator: Option<&PyBytes>,
+        quotechar: Option<&PyBytes>,
+        escapechar: Option<&PyBytes>,
+        doublequote: Option<bool>,
+        quote_style: Option<String>,
+        comment: Option<&PyBytes>,
+    ) -> PyResult<()> {
+        if let Some(delimiter) = delimiter {
+            self.delimiter = get_single_byte(delimiter)?;
+        }
+        if let Some(terminator) = terminator {
+            self.terminator = get_terminator(terminator)?;
+        }
+        if let Some(quotechar) = quotechar {
+            self.quote = get_single_byte(quotechar)?;
+        }
+        if escapechar.is_some() {
+            self.escape = get_single_byte_option(escapechar)?;
+        }
+        if let Some(doublequote) = doublequote {
+            self.double_quote = doublequote;
+        }
+        if let Some(quote_style) = quote_style {
+            parse_quote_style(&quote_style)?;
+            self.quote_style = quote_style;
+        }
+        if comment.is_some() {
+            self.comment = get_single_byte_option(comment)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the option `name`, passed as a keyword argument to `Dialect`.
+    ///
+    /// `None` disables `escapechar` and `comment`, and keeps the other
+    /// options, which can't be disabled.
+    fn set_option(&mut self, name: &str, value: &PyObjectRef) -> PyResult<()> {
+        match name {
+            "escapechar" => self.escape = get_single_byte_option(value.extract()?)?,
+            "comment" => self.comment = get_single_byte_option(value.extract()?)?,
+            "delimiter" | "terminator" | "quotechar" | "doublequote" | "quote_style"
+                if value.is_none() => {}
+            "delimiter" => self.delimiter = get_single_byte(value.extract()?)?,
+            "terminator" => self.terminator = get_terminator(value.extract()?)?,
+            "quotechar" => self.quote = get_single_byte(value.extract()?)?,
+            "doublequote" => self.double_quote = value.extract()?,
+            "quote_style" => {
+                let quote_style: String = value.extract()?;
+                parse_quote_style(&quote_style)?;
+                self.quote_style = quote_style;
+            }
+            _ => {
+                return Err(exc::TypeError::py_err(format!(
+                    "Unexpected Dialect option: {:?}",
+                    name
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether fields may be quoted when reading.
+    pub fn quoting(&self) -> bool {
+        self.quote_style != "never"
+    }
+
+    /// Builds a [csv::ReaderBuilder] for the dialect.
+    pub fn reader_builder(&self, quoting: bool) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .terminator(self.terminator)
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.double_quote)
+            .quoting(quoting)
+            .comment(self.comment);
+        builder
+    }
+
+    /// Builds a [csv::WriterBuilder] for the dialect.
+    pub fn writer_builder(&self) -> PyResult<csv::WriterBuilder> {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .terminator(self.terminator)
+            .quote(self.quote)
+            // Only used if `double_quote` is not set.
+            .escape(self.escape.unwrap_or(b'\\'))
+            .double_quote(self.double_quote)
+            .quote_style(parse_quote_style(&self.quote_style)?)
+            .comment(self.comment);
+        Ok(builder)
+    }
+}
+
+/// Implements the Python type methods for `Dialect`
+#[pymethods]
+impl Dialect {
+    /// Dialect(dialect: Union[Dialect, str, Type[csv.Dialect]], *, delimiter: bytes, terminator: bytes, quotechar: bytes, escapechar: bytes, doublequote: bool, quote_style: str, comment: bytes)
+    /// --
+    /// Creates a new Dialect, from `dialect` with the keyword arguments
+    /// overriding its options. Options that are not passed, or `None`, are
+    /// kept, except that `None` disables `escapechar` and `comment`.
+    ///
+    /// - `dialect` - A `Dialect`, the name of a registered dialect, or a
+    ///   subclass or instance of the stdlib's `csv.Dialect`.
+    /// - `delimiter` - CSV field separator, defaults to `b","`.
+    /// - `terminator` - CSV record terminator, either a single byte or
+    ///   `b"\r\n"`. Defaults to `b"\n"`.
+    /// - `quotechar` - CSV quote character, defaults to `b'"'`.
+    /// - `escapechar` - Escape character for quotes, disabled by default.
+    /// - `doublequote` - Treat two consecutive quotes as an escaped quote,
+    ///   defaults to `True`.
+    /// - `quote_style` - When to quote fields when writing, one of
+    ///   `"necessary"`, `"always"`, `"never"` or `"non_numeric"`. `"never"`
+    ///   also disables quoting when reading. Defaults to `"necessary"`.
+    /// - `comment` - Records starting with this byte are skipped when
+    ///   reading, disabled by default.
+    #[new]
+    #[args(options = "**")]
+    fn __new__(
+        obj: &PyRawObject,
+        dialect: Option<&PyObjectRef>,
+        options: Option<&PyDict>,
+        py: Python,
+    ) -> PyResult<()> {
+        let mut dialect = Dialect::from_object(py, dialect)?;
+        // The options are keyword arguments, so that passing `None` can be
+        // told apart from not passing an option.
+        if let Some(options) = options {
+            for (name, value) in options.iter() {
+                dialect.set_option(&name.extract::<String>()?, value)?;
+            }
+        }
+        obj.init(|| dialect)
+    }
+
+    #[getter]
+    fn delimiter(&self) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        Ok(PyBytes::new(gil.python(), &[self.delimiter]).into())
+    }
+
+    #[getter]
+    fn terminator(&self) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        Ok(PyBytes::new(gil.python(), &terminator_bytes(self.terminator)).into())
+    }
+
+    #[getter]
+    fn quotechar(&self) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        Ok(PyBytes::new(gil.python(), &[self.quote]).into())
+    }
+
+    #[getter]
+    fn escapechar(&self) -> PyResult<Option<PyObject>> {
+        let gil = Python::acquire_gil();
+        Ok(self
+            .escape
+            .map(|escape| PyBytes::new(gil.python(), &[escape]).into()))
+    }
+
+    #[getter]
+    fn doublequote(&self) -> PyResult<bool> {
+        Ok(self.double_quote)
+    }
+
+    #[getter]
+    fn quote_style(&self) -> PyResult<String> {
+        Ok(self.quote_style.clone())
+    }
+
+    #[getter]
+    fn comment(&self) -> PyResult<Option<PyObject>> {
+        let gil = Python::acquire_gil();
+        Ok(self
+            .comment
+            .map(|comment| PyBytes::new(gil.python(), &[comment]).into()))
+    }
+}
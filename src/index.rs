@@ -0,0 +1,133 @@
+//! An on-disk index of record positions, for random access to the records of
+//! a CSV file.
+//!
+//! The index is in the format of `RandomAccessSimple` from the [csv-index]
+//! crate: the byte offset of every record, including the header, as a
+//! big-endian `u64`, followed by the number of records.
+//!
+//!  [csv-index]: https://docs.rs/csv-index
+extern crate csv;
+extern crate pyo3;
+
+use compression::Compression;
+use dialect::Dialect;
+use pyo3::exceptions as exc;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyObjectRef};
+use pyo3::PyObject;
+use pyo3::PyResult;
+use pyo3::Python;
+use reader::{csv_error_to_pyerr, make_error_position, CSVSource, UNKNOWN_LINE};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+
+/// Writes `value` as a big-endian `u64`.
+fn write_u64<W: Write>(wtr: &mut W, value: u64) -> io::Result<()> {
+    let mut buf = [0u8; 8];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (value >> (56 - 8 * i)) as u8;
+    }
+    wtr.write_all(&buf)
+}
+
+/// Reads a big-endian `u64`.
+fn read_u64<R: Read>(rdr: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    rdr.read_exact(&mut buf)?;
+    Ok(buf.iter().fold(0, |value, &b| (value << 8) | u64::from(b)))
+}
+
+/// Writes the index of the records read from `rdr` to `wtr`, returning the
+/// number of records.
+fn write_index<R: Read, W: Write>(rdr: &mut csv::Reader<R>, wtr: &mut W) -> csv::Result<u64> {
+    let mut record = csv::ByteRecord::new();
+    let mut count = 0;
+    while rdr.read_byte_record(&mut record)? {
+        let pos = record.position().expect("position of a read record");
+        write_u64(wtr, pos.byte())?;
+        count += 1;
+    }
+    write_u64(wtr, count)?;
+    Ok(count)
+}
+
+/// build_index(path, index_path, delimiter=None, dialect=None)
+/// --
+/// Writes an index of the byte offsets of the records of the CSV file at
+/// `path` to `index_path`, returning the number of records.
+///
+/// The header, if any, is indexed as record `0`, like the record numbers of
+/// `CSVReader.position`. Compressed files are not supported, as
+/// `CSVReader.seek` can't seek in them.
+///
+/// - `path` - Path to the CSV file.
+/// - `index_path` - Path to write the index to.
+/// - `delimiter` - CSV field separator, defaults to `b','`.
+/// - `dialect` - CSV dialect, as passed to `CSVReader`, `delimiter`
+///   overrides its delimiter.
+#[pyfunction]
+pub fn build_index(
+    path: String,
+    index_path: String,
+    delimiter: Option<&PyBytes>,
+    dialect: Option<&PyObjectRef>,
+    py: Python,
+) -> PyResult<u64> {
+    let mut csv_dialect = Dialect::from_object(py, dialect)?;
+    csv_dialect.update(delimiter, None, None, None, None, None, None)?;
+    let compression = CSVSource::Path(path.clone()).detect_compression(Compression::Infer)?;
+    if compression != Compression::None {
+        return Err(exc::ValueError::py_err(
+            "build_index is not supported for compressed files",
+        ));
+    }
+    let mut builder = csv_dialect.reader_builder(csv_dialect.quoting());
+    builder.flexible(true).has_headers(false);
+    let result = py.allow_threads(move || -> csv::Result<u64> {
+        let mut rdr = builder.from_path(path)?;
+        let mut wtr = BufWriter::new(File::create(index_path)?);
+        let count = write_index(&mut rdr, &mut wtr)?;
+        wtr.flush()?;
+        Ok(count)
+    });
+    match result {
+        Ok(count) => Ok(count),
+        Err(error) => Err(csv_error_to_pyerr(error)?),
+    }
+}
+
+/// index_position(index_path, record)
+/// --
+/// Looks up the position of a record in an index written by `build_index`,
+/// returning a `rustcsv.error.Position` that can be passed to
+/// `CSVReader.seek`.
+///
+/// The index does not store line numbers, the `line` of the position is
+/// `None`.
+///
+/// - `index_path` - Path to the index.
+/// - `record` - Number of the record, the header is record `0`.
+#[pyfunction]
+pub fn index_position(index_path: String, record: u64) -> PyResult<PyObject> {
+    let mut file = File::open(index_path)?;
+    file.seek(SeekFrom::End(-8))?;
+    let count = read_u64(&mut file)?;
+    if record >= count {
+        return Err(exc::IndexError::py_err(format!(
+            "Record {} out of range, the index has {} records",
+            record, count
+        )));
+    }
+    file.seek(SeekFrom::Start(record * 8))?;
+    let mut pos = csv::Position::new();
+    pos.set_byte(read_u64(&mut file)?)
+        .set_line(UNKNOWN_LINE)
+        .set_record(record);
+    make_error_position(pos)
+}
+
+/// Adds the functions of this module to the `_rustcsv` module.
+pub fn register(m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_function!(build_index))?;
+    m.add_function(wrap_function!(index_position))
+}
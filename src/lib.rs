@@ -1,17 +1,34 @@
 #![feature(specialization)]
 
 extern crate built;
+extern crate bzip2;
 extern crate csv;
+extern crate encoding_rs;
+extern crate encoding_rs_io;
 extern crate env_logger;
+extern crate flate2;
 #[macro_use]
 extern crate log;
 extern crate tempfile;
+extern crate xz2;
+extern crate zstd;
 #[macro_use]
 extern crate pyo3;
 
+pub mod arrow;
+pub mod compression;
+pub mod convert;
+pub mod dialect;
+pub mod index;
+pub mod missing;
+pub mod mmap;
+pub mod numpy;
+pub mod parallel;
 pub mod py_file;
 pub mod reader;
 pub mod record;
+pub mod sniff;
+pub mod threaded;
 pub mod util;
 pub mod writer;
 
@@ -91,6 +108,12 @@ pub fn rustcsv(_py: Python, m: &PyModule) -> PyResult<()> {
     env_logger::Builder::from_env("RUSTCSV_LOG").init();
     m.add_class::<reader::CSVReader>()?;
     m.add_class::<writer::CSVWriter>()?;
+    m.add_class::<writer::CSVDictWriter>()?;
+    m.add_class::<dialect::Dialect>()?;
+    arrow::register(m)?;
+    index::register(m)?;
+    numpy::register(m)?;
+    sniff::register(m)?;
     m.add::<PyObject>("__build__", pyo3_built!(_py, built_info))?;
     Ok(())
 }
@@ -0,0 +1,306 @@
+extern crate csv;
+extern crate pyo3;
+
+use pyo3::exceptions as exc;
+use pyo3::PyResult;
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+/// For each record read by a [QuoteScanner], its byte offset, as in
+/// [csv::Position::byte], and which of its fields were quoted.
+pub type QuotedFields = Arc<Mutex<VecDeque<(u64, Vec<bool>)>>>;
+
+/// The `na_values` of a `CSVReader`, fields matching any of them are returned
+/// as `None`.
+pub struct NaValues {
+    values: Vec<Vec<u8>>,
+    /// Set if quoted fields never match, filled in by a [QuoteScanner].
+    quoted_fields: Option<QuotedFields>,
+}
+
+impl NaValues {
+    /// # Arguments
+    ///
+    /// * `values` - Field values to treat as missing.
+    /// * `quoted` - Whether quoted fields may match `values`. If `false`, the
+    ///   source must be read through the [QuoteScanner] returned by
+    ///   [NaValues::quote_scanner].
+    pub fn new(values: Vec<String>, quoted: bool) -> NaValues {
+        NaValues {
+            values: values.into_iter().map(String::into_bytes).collect(),
+            quoted_fields: if quoted {
+                None
+            } else {
+                Some(Arc::new(Mutex::new(VecDeque::new())))
+            },
+        }
+    }
+
+    /// Returns a [QuoteScannerConfig] if quoted fields never match.
+    pub fn quote_scanner(&self, dialect: ScanDialect) -> Option<QuoteScannerConfig> {
+        self.quoted_fields
+            .as_ref()
+            .map(|quoted_fields| QuoteScannerConfig {
+                dialect,
+                quoted_fields: quoted_fields.clone(),
+            })
+    }
+
+    /// Finds the missing fields of `r`, returning `None` if there are none.
+    ///
+    /// Records must be passed in the order they're read, but may be skipped,
+    /// e.g. if they failed to parse. A `RuntimeError` is raised if the
+    /// [QuoteScanner] did not find a record at the position of `r`.
+    pub fn find_missing(&self, r: &csv::ByteRecord) -> PyResult<Option<Vec<bool>>> {
+        let quoted = match self.quoted_fields {
+            Some(ref quoted_fields) => Some(take_quoted_fields(quoted_fields, r)?),
+            None => None,
+        };
+        let missing: Vec<bool> = r
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let is_quoted = quoted
+                    .as_ref()
+                    .and_then(|q| q.get(i).cloned())
+                    .unwrap_or(false);
+                !is_quoted && self.values.iter().any(|value| value.as_slice() == field)
+            })
+            .collect();
+        if missing.contains(&true) {
+            Ok(Some(missing))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Takes the quoted fields of `r` from `quoted_fields`, dropping those of any
+/// records before it that were not returned.
+fn take_quoted_fields(quoted_fields: &QuotedFields, r: &csv::ByteRecord) -> PyResult<Vec<bool>> {
+    let offset = r.position().map_or(0, |pos| pos.byte());
+    let mut quoted_fields = quoted_fields.lock().unwrap();
+    while quoted_fields
+        .front()
+        .map_or(false, |&(start, _)| start < offset)
+    {
+        quoted_fields.pop_front();
+    }
+    match quoted_fields.pop_front() {
+        Some((start, quoted)) if start == offset => Ok(quoted),
+        _ => Err(exc::RuntimeError::py_err(format!(
+            "Could not find the quoted fields of the record at byte {}",
+            offset
+        ))),
+    }
+}
+
+/// The parts of the CSV dialect a [QuoteScanner] needs to find the quoted
+/// fields, must match the [csv::ReaderBuilder] used to parse the records.
+#[derive(Clone, Copy, Debug)]
+pub struct ScanDialect {
+    pub delimiter: u8,
+    pub terminator: csv::Terminator,
+    pub quote: u8,
+    pub escape: Option<u8>,
+    pub double_quote: bool,
+    pub comment: Option<u8>,
+    /// Whether quote characters are special, if not, no field is quoted.
+    pub quoting: bool,
+    /// Whether the first record is the header, it's not added to the
+    /// [QuotedFields].
+    pub has_headers: bool,
+}
+
+impl ScanDialect {
+    /// The state of the CSV parser after reading `b` in `state`.
+    ///
+    /// This follows the transitions of the `csv_core` parser, with its
+    /// states that don't read a byte left out.
+    pub fn next_state(&self, state: ScanState, b: u8) -> ScanState {
+        use self::ScanState::*;
+        match state {
+            StartRecord | EndRecord => self.start_record(b),
+            StartField => self.start_field(b),
+            InField => self.in_field(b),
+            InQuotedField if self.quoting && b == self.quote => InDoubleEscapedQuote,
+            InQuotedField if self.quoting && Some(b) == self.escape => InEscapedQuote,
+            InQuotedField | InEscapedQuote => InQuotedField,
+            InDoubleEscapedQuote if self.quoting && self.double_quote && b == self.quote => {
+                InQuotedField
+            }
+            InDoubleEscapedQuote => self.in_field(b),
+            // Comments end at a `\n` regardless of the terminator.
+            InComment if b == b'\n' => StartRecord,
+            InComment => InComment,
+        }
+    }
+
+    fn is_terminator(&self, b: u8) -> bool {
+        match self.terminator {
+            csv::Terminator::Any(t) => b == t,
+            _ => b == b'\r' || b == b'\n',
+        }
+    }
+
+    /// Handles a byte at the start of a record, empty lines are skipped.
+    fn start_record(&self, b: u8) -> ScanState {
+        if self.is_terminator(b) {
+            ScanState::StartRecord
+        } else if Some(b) == self.comment {
+            ScanState::InComment
+        } else {
+            self.start_field(b)
+        }
+    }
+
+    fn start_field(&self, b: u8) -> ScanState {
+        if self.quoting && b == self.quote {
+            ScanState::InQuotedField
+        } else {
+            self.in_field(b)
+        }
+    }
+
+    /// Handles a byte outside of quotes.
+    fn in_field(&self, b: u8) -> ScanState {
+        if b == self.delimiter {
+            ScanState::StartField
+        } else if self.is_terminator(b) {
+            ScanState::EndRecord
+        } else {
+            ScanState::InField
+        }
+    }
+}
+
+pub struct QuoteScannerConfig {
+    dialect: ScanDialect,
+    quoted_fields: QuotedFields,
+}
+
+impl QuoteScannerConfig {
+    pub fn wrap<R: io::Read>(self, inner: R) -> QuoteScanner<R> {
+        QuoteScanner {
+            inner,
+            skip_header: self.dialect.has_headers,
+            dialect: self.dialect,
+            quoted_fields: self.quoted_fields,
+            state: ScanState::StartRecord,
+            offset: 0,
+            record_start: 0,
+            fields: Vec::new(),
+        }
+    }
+}
+
+/// The states of the CSV parser that matter for finding quoted fields and
+/// record boundaries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScanState {
+    /// Before a record, or in the empty lines between records.
+    StartRecord,
+    StartField,
+    InField,
+    InQuotedField,
+    /// After an escape character in a quoted field.
+    InEscapedQuote,
+    /// After a quote in a quoted field, which either ends the field or is
+    /// the first of two quotes.
+    InDoubleEscapedQuote,
+    InComment,
+    /// After the terminator that ended a record.
+    EndRecord,
+}
+
+impl ScanState {
+    /// Whether the parser is between records.
+    pub fn is_record_start(self) -> bool {
+        self == ScanState::StartRecord || self == ScanState::EndRecord
+    }
+}
+
+/// Passes through the bytes read from `inner`, while following the states
+/// of the CSV parser to record which fields of each record are quoted.
+///
+/// `csv` does not tell whether a field was quoted, which is needed to tell
+/// `""` apart from an empty field.
+pub struct QuoteScanner<R> {
+    inner: R,
+    dialect: ScanDialect,
+    quoted_fields: QuotedFields,
+    skip_header: bool,
+    state: ScanState,
+    /// Byte offset of the next byte read.
+    offset: u64,
+    /// Byte offset of the current record, where the parser starts reading
+    /// it, i.e. after the previous record.
+    record_start: u64,
+    /// Whether the fields of the current record are quoted.
+    fields: Vec<bool>,
+}
+
+impl<R> QuoteScanner<R> {
+    fn scan(&mut self, data: &[u8]) {
+        use self::ScanState::*;
+        for &b in data {
+            let state = self.dialect.next_state(self.state, b);
+            let starts_field = match (self.state, state) {
+                (StartRecord, StartRecord) | (StartRecord, InComment) => false,
+                (EndRecord, StartRecord) | (EndRecord, InComment) => false,
+                (StartRecord, _) | (EndRecord, _) | (StartField, _) => true,
+                _ => false,
+            };
+            if starts_field {
+                self.fields.push(state == InQuotedField);
+            }
+            self.offset += 1;
+            if state == EndRecord {
+                self.end_record();
+            }
+            self.state = state;
+        }
+    }
+
+    fn end_record(&mut self) {
+        let fields = mem::replace(&mut self.fields, Vec::new());
+        if self.skip_header {
+            self.skip_header = false;
+        } else {
+            self.quoted_fields
+                .lock()
+                .unwrap()
+                .push_back((self.record_start, fields));
+        }
+        self.record_start = self.offset;
+    }
+
+    /// Handles the end of the input, which also ends the last record.
+    fn end(&mut self) {
+        match self.state {
+            ScanState::StartRecord | ScanState::EndRecord | ScanState::InComment => (),
+            ScanState::StartField => {
+                self.fields.push(false);
+                self.end_record();
+            }
+            _ => self.end_record(),
+        }
+        self.state = ScanState::StartRecord;
+    }
+}
+
+impl<R: io::Read> io::Read for QuoteScanner<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 {
+            if !buf.is_empty() {
+                self.end();
+            }
+        } else {
+            self.scan(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,100 @@
+//! Memory-mapped CSV files.
+//!
+//! Files are mapped with Python's [mmap] module rather than in Rust, so that
+//! fields can be returned as `memoryview` slices of the mapping without
+//! copying them. The mapping is read through its buffer, which also keeps it
+//! from being closed while the [MappedFile] is alive.
+//!
+//!  [mmap]: https://docs.python.org/3/library/mmap.html
+extern crate pyo3;
+
+use pyo3::buffer::PyBuffer;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PySlice};
+use pyo3::PyObject;
+use pyo3::PyResult;
+use pyo3::Python;
+use std::fs;
+use std::slice;
+use std::sync::Arc;
+
+struct Mapping {
+    /// Buffer of the `mmap.mmap` object, `None` for an empty file, which
+    /// can't be mapped.
+    buffer: Option<PyBuffer>,
+    /// A `memoryview` of the whole mapping, sliced to get the views of
+    /// fields.
+    view: PyObject,
+    /// The builtin `memoryview` type, for fields that are not in the
+    /// mapping as-is.
+    memoryview: PyObject,
+}
+
+/// A read-only memory-mapped file, which is cheap to clone.
+///
+/// [io::Cursor](std::io::Cursor) reads and seeks in the mapped bytes.
+#[derive(Clone)]
+pub struct MappedFile {
+    inner: Arc<Mapping>,
+}
+
+impl MappedFile {
+    /// Maps the file at `path` for reading.
+    pub fn open(py: Python, path: &str) -> PyResult<MappedFile> {
+        let builtins = py.import("builtins")?;
+        let memoryview = builtins.get("memoryview")?;
+        let (buffer, view) = if fs::metadata(path)?.len() == 0 {
+            (None, memoryview.call1((PyBytes::new(py, b""),))?)
+        } else {
+            let mmap_mod = py.import("mmap")?;
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("access", mmap_mod.get("ACCESS_READ")?)?;
+            // The mapping doesn't need the file to stay open.
+            let file = builtins.call1("open", (path, "rb"))?;
+            let fileno = file.call_method0("fileno")?;
+            let mapping = mmap_mod.call("mmap", (fileno, 0), Some(kwargs));
+            file.call_method0("close")?;
+            let mapping = mapping?;
+            (
+                Some(PyBuffer::get(py, mapping)?),
+                memoryview.call1((mapping,))?,
+            )
+        };
+        Ok(MappedFile {
+            inner: Arc::new(Mapping {
+                buffer,
+                view: view.to_object(py),
+                memoryview: memoryview.to_object(py),
+            }),
+        })
+    }
+
+    /// Whether `field` is in the mapping at `offset`.
+    pub fn contains(&self, offset: usize, field: &[u8]) -> bool {
+        self.as_ref().get(offset..offset + field.len()) == Some(field)
+    }
+
+    /// Returns a `memoryview` of `len` bytes of the mapping at `offset`.
+    pub fn view(&self, py: Python, offset: usize, len: usize) -> PyResult<PyObject> {
+        let slice = PySlice::new(py, offset as isize, (offset + len) as isize, 1);
+        Ok(self.inner.view.as_ref(py).get_item(slice)?.to_object(py))
+    }
+
+    /// Returns a `memoryview` of a copy of `field`.
+    pub fn copy(&self, py: Python, field: &[u8]) -> PyResult<PyObject> {
+        self.inner.memoryview.call1(py, (PyBytes::new(py, field),))
+    }
+}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        match self.inner.buffer {
+            // The mapping can't be closed or resized while its buffer is
+            // held, and it's read-only.
+            Some(ref buffer) => unsafe {
+                slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes())
+            },
+            None => &[],
+        }
+    }
+}
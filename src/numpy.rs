@@ -0,0 +1,424 @@
+//! Reading numeric CSV into NumPy arrays.
+extern crate csv;
+extern crate pyo3;
+
+use compression::Compression;
+use convert::{column_index, conversion_error, parse_bool, DType};
+use pyo3::exceptions as exc;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use pyo3::types::PyDict;
+use pyo3::types::PyObjectRef;
+use pyo3::PyObject;
+use pyo3::PyResult;
+use pyo3::Python;
+use reader::{
+    csv_error_to_pyerr, make_optional_error_position, make_records_iterator, CSVSource,
+    UnequalLengthsError,
+};
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::str;
+use util::get_optional_single_byte;
+
+/// The NumPy scalar types fields can be parsed as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    F64,
+    F32,
+    I64,
+    I32,
+    I16,
+    I8,
+    U64,
+    U32,
+    U16,
+    U8,
+    Bool,
+}
+
+impl Kind {
+    /// Determines the [Kind] of a `numpy.dtype` from its `kind` and
+    /// `itemsize`.
+    fn from_dtype(dtype: &PyObjectRef) -> PyResult<Kind> {
+        let kind: String = dtype.getattr("kind")?.extract()?;
+        let itemsize: usize = dtype.getattr("itemsize")?.extract()?;
+        let byteorder: String = dtype.getattr("byteorder")?.extract()?;
+        let native = if cfg!(target_endian = "little") {
+            "<"
+        } else {
+            ">"
+        };
+        let is_native = byteorder == "=" || byteorder == "|" || byteorder == native;
+        let parsed = match (kind.as_str(), itemsize) {
+            ("f", 8) => Some(Kind::F64),
+            ("f", 4) => Some(Kind::F32),
+            ("i", 8) => Some(Kind::I64),
+            ("i", 4) => Some(Kind::I32),
+            ("i", 2) => Some(Kind::I16),
+            ("i", 1) => Some(Kind::I8),
+            ("u", 8) => Some(Kind::U64),
+            ("u", 4) => Some(Kind::U32),
+            ("u", 2) => Some(Kind::U16),
+            ("u", 1) => Some(Kind::U8),
+            ("b", 1) => Some(Kind::Bool),
+            _ => None,
+        };
+        match parsed {
+            Some(kind) if is_native => Ok(kind),
+            _ => Err(exc::ValueError::py_err(format!(
+                "Unsupported dtype: {}",
+                dtype.str()?.to_string()?
+            ))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Kind::F64 | Kind::I64 | Kind::U64 => 8,
+            Kind::F32 | Kind::I32 | Kind::U32 => 4,
+            Kind::I16 | Kind::U16 => 2,
+            Kind::I8 | Kind::U8 | Kind::Bool => 1,
+        }
+    }
+
+    /// The [DType] reported in a `rustcsv.error.ConversionError`.
+    fn dtype(self) -> DType {
+        match self {
+            Kind::F64 | Kind::F32 => DType::Float,
+            Kind::Bool => DType::Bool,
+            _ => DType::Int,
+        }
+    }
+
+    /// Parses `text` and writes it to `buf` at `offset`, returning `None` if
+    /// `text` is not a valid value.
+    fn write(self, buf: &mut [u8], offset: usize, text: &str) -> Option<()> {
+        match self {
+            Kind::F64 => write_value::<f64>(buf, offset, text.parse().ok()?),
+            Kind::F32 => write_value::<f32>(buf, offset, text.parse().ok()?),
+            Kind::I64 => write_value::<i64>(buf, offset, text.parse().ok()?),
+            Kind::I32 => write_value::<i32>(buf, offset, text.parse().ok()?),
+            Kind::I16 => write_value::<i16>(buf, offset, text.parse().ok()?),
+            Kind::I8 => write_value::<i8>(buf, offset, text.parse().ok()?),
+            Kind::U64 => write_value::<u64>(buf, offset, text.parse().ok()?),
+            Kind::U32 => write_value::<u32>(buf, offset, text.parse().ok()?),
+            Kind::U16 => write_value::<u16>(buf, offset, text.parse().ok()?),
+            Kind::U8 => write_value::<u8>(buf, offset, text.parse().ok()?),
+            Kind::Bool => write_value::<u8>(buf, offset, parse_bool(text)? as u8),
+        }
+        Some(())
+    }
+}
+
+fn write_value<T: Copy>(buf: &mut [u8], offset: usize, value: T) {
+    assert!(offset + mem::size_of::<T>() <= buf.len());
+    unsafe { ptr::write_unaligned(buf.as_mut_ptr().add(offset) as *mut T, value) }
+}
+
+/// Where the field of a column is written in a row of the array.
+struct Field {
+    column: usize,
+    kind: Kind,
+    offset: usize,
+}
+
+/// The layout of a row of the array.
+struct RowLayout {
+    fields: Vec<Field>,
+    size: usize,
+}
+
+impl RowLayout {
+    /// A row of a structured `dtype`, with a field per column.
+    fn structured(dtype: &PyObjectRef, columns: &[usize]) -> PyResult<RowLayout> {
+        let names: Vec<String> = dtype.getattr("names")?.extract()?;
+        if names.len() != columns.len() {
+            return Err(exc::ValueError::py_err(format!(
+                "dtype has {} fields, but {} columns are used",
+                names.len(),
+                columns.len()
+            )));
+        }
+        let dtype_fields = dtype.getattr("fields")?;
+        let mut fields = Vec::with_capacity(names.len());
+        for (name, &column) in names.iter().zip(columns.iter()) {
+            // `(dtype, offset)`, followed by the title of a titled field.
+            let dtype_field = dtype_fields.get_item(name.as_str())?;
+            fields.push(Field {
+                column,
+                kind: Kind::from_dtype(dtype_field.get_item(0)?)?,
+                offset: dtype_field.get_item(1)?.extract()?,
+            });
+        }
+        Ok(RowLayout {
+            fields,
+            size: dtype.getattr("itemsize")?.extract()?,
+        })
+    }
+
+    /// A row of a 2-D array, with an element per column.
+    fn homogeneous(kind: Kind, columns: &[usize]) -> RowLayout {
+        RowLayout {
+            fields: columns
+                .iter()
+                .enumerate()
+                .map(|(i, &column)| Field {
+                    column,
+                    kind,
+                    offset: i * kind.size(),
+                })
+                .collect(),
+            size: columns.len() * kind.size(),
+        }
+    }
+}
+
+/// Number of rows the array is first allocated with.
+const INITIAL_ROWS: usize = 1024;
+
+/// The NumPy array the rows are written into, grown as they are read.
+struct RowArray<'p> {
+    array: &'p PyObjectRef,
+    /// Number of elements of a row of a 2-D array, `None` if structured.
+    columns: Option<usize>,
+    row_size: usize,
+    rows: usize,
+    capacity: usize,
+    data: *mut u8,
+}
+
+impl<'p> RowArray<'p> {
+    fn new(
+        numpy: &'p PyModule,
+        dtype: &PyObjectRef,
+        structured: bool,
+        layout: &RowLayout,
+    ) -> PyResult<RowArray<'p>> {
+        let columns = if structured {
+            None
+        } else {
+            Some(layout.fields.len())
+        };
+        let array = numpy.call1("empty", (shape(0, columns), dtype))?;
+        let mut array = RowArray {
+            array,
+            columns,
+            row_size: layout.size,
+            rows: 0,
+            capacity: 0,
+            data: ptr::null_mut(),
+        };
+        array.resize(INITIAL_ROWS)?;
+        Ok(array)
+    }
+
+    /// Resizes the array in place to `rows` rows, keeping its data.
+    fn resize(&mut self, rows: usize) -> PyResult<()> {
+        let py = self.array.py();
+        let kwargs = PyDict::new(py);
+        // The array has not been shared, so nothing refers to its data.
+        kwargs.set_item("refcheck", false)?;
+        self.array
+            .call_method("resize", (shape(rows, self.columns),), Some(kwargs))?;
+        self.data = self
+            .array
+            .getattr("__array_interface__")?
+            .get_item("data")?
+            .get_item(0)?
+            .extract::<usize>()? as *mut u8;
+        self.capacity = rows;
+        Ok(())
+    }
+
+    /// Adds a row of zeros, returning its bytes.
+    fn push_row(&mut self) -> PyResult<&mut [u8]> {
+        if self.rows == self.capacity {
+            let capacity = self.capacity * 2;
+            self.resize(capacity)?;
+        }
+        let row = unsafe {
+            let start = self.data.add(self.rows * self.row_size);
+            ptr::write_bytes(start, 0, self.row_size);
+            slice::from_raw_parts_mut(start, self.row_size)
+        };
+        self.rows += 1;
+        Ok(row)
+    }
+
+    /// Shrinks the array to the rows that were added.
+    fn finish(mut self) -> PyResult<&'p PyObjectRef> {
+        let rows = self.rows;
+        self.resize(rows)?;
+        Ok(self.array)
+    }
+}
+
+/// The shape of an array of `rows` rows.
+fn shape(rows: usize, columns: Option<usize>) -> Vec<usize> {
+    match columns {
+        Some(columns) => vec![rows, columns],
+        None => vec![rows],
+    }
+}
+
+/// read_numpy(path_or_fd, dtype=None, usecols=None, delimiter=None, has_headers=None)
+/// --
+/// Reads a CSV of numbers into a NumPy array.
+///
+/// The fields are parsed directly into the buffer of the array, without
+/// creating Python objects for the fields.
+///
+/// - `path_or_fd` - Either a string path to a file or a [binary file],
+///   which is decompressed if it is compressed.
+/// - `dtype` - NumPy dtype of the array, defaults to `float64`. A 2-D array
+///   is returned for a scalar dtype, e.g. `float64`, `int32` or `bool`, and
+///   a 1-D structured array for a structured dtype, with a field per column.
+/// - `usecols` - Indices or names of the columns to read, defaults to all
+///   columns.
+/// - `delimiter` - CSV field separator, defaults to `b','`.
+/// - `has_headers` - Whether the first record is the header, defaults to
+///   `False`. Required to select `usecols` by name.
+///
+///  [binary file]: https://docs.python.org/3/glossary.html#term-binary-file
+#[pyfunction]
+pub fn read_numpy(
+    path_or_fd: &PyObjectRef,
+    dtype: Option<&PyObjectRef>,
+    usecols: Option<&PyObjectRef>,
+    delimiter: Option<&PyBytes>,
+    has_headers: Option<bool>,
+    py: Python,
+) -> PyResult<PyObject> {
+    let has_headers = has_headers.unwrap_or(false);
+    let numpy = py.import("numpy")?;
+    let dtype = match dtype {
+        Some(dtype) => numpy.call1("dtype", (dtype,))?,
+        None => numpy.call1("dtype", ("float64",))?,
+    };
+    let structured = !dtype.getattr("names")?.is_none();
+
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .delimiter(get_optional_single_byte(delimiter, b',')?)
+        .flexible(true)
+        .has_headers(has_headers);
+    let source = CSVSource::from_object(py, path_or_fd.to_object(py))?;
+    let (header, iter) = match make_records_iterator(
+        source,
+        Compression::Infer,
+        None,
+        &builder,
+        has_headers,
+        None,
+        false,
+        None,
+    ) {
+        Ok(res) => res,
+        Err(error) => return Err(csv_error_to_pyerr(error)?),
+    };
+    let fieldnames: Option<Vec<String>> = header.map(|h| {
+        h.iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect()
+    });
+    let fieldnames = fieldnames.as_ref().map(|f| f.as_slice());
+
+    let columns: Option<Vec<usize>> = match usecols {
+        Some(usecols) => {
+            let mut columns = Vec::new();
+            for column in usecols.iter()? {
+                columns.push(column_index(py, column?, fieldnames)?);
+            }
+            Some(columns)
+        }
+        None if structured => {
+            let len: usize = dtype.getattr("names")?.len()?;
+            Some((0..len).collect())
+        }
+        None => None,
+    };
+    // Without usecols, all columns of the first record are read, and every
+    // record must be of the same length.
+    let expected_len = match columns {
+        Some(_) => None,
+        None => fieldnames.map(|f| f.len()),
+    };
+    let mut layout = match (structured, &columns) {
+        (true, &Some(ref columns)) => Some(RowLayout::structured(dtype, columns)?),
+        (false, &Some(ref columns)) => {
+            Some(RowLayout::homogeneous(Kind::from_dtype(dtype)?, columns))
+        }
+        (_, &None) => None,
+    };
+    let kind = if structured {
+        None
+    } else {
+        Some(Kind::from_dtype(dtype)?)
+    };
+
+    let mut array = match layout {
+        Some(ref layout) => Some(RowArray::new(numpy, dtype, structured, layout)?),
+        None => None,
+    };
+    let mut expected_len = expected_len;
+    for result in iter {
+        let r = match result {
+            Ok(r) => r,
+            Err(error) => return Err(csv_error_to_pyerr(error)?),
+        };
+        if layout.is_none() {
+            let columns: Vec<usize> = (0..r.len()).collect();
+            let row_layout = RowLayout::homogeneous(kind.unwrap(), &columns);
+            array = Some(RowArray::new(numpy, dtype, structured, &row_layout)?);
+            layout = Some(row_layout);
+            expected_len = Some(r.len());
+        }
+        let layout = layout.as_ref().unwrap();
+        let min_len = layout
+            .fields
+            .iter()
+            .map(|f| f.column + 1)
+            .max()
+            .unwrap_or(0);
+        if expected_len.map_or(false, |len| len != r.len()) || r.len() < min_len {
+            return Err(UnequalLengthsError::py_err((
+                format!(
+                    "Unequal lengths: Expected length {:?} got length {:?}",
+                    expected_len.unwrap_or(min_len),
+                    r.len(),
+                ),
+                make_optional_error_position(r.position())?,
+            )));
+        }
+
+        let row = array.as_mut().unwrap().push_row()?;
+        for field in layout.fields.iter() {
+            let value = &r[field.column];
+            let written = str::from_utf8(value)
+                .ok()
+                .and_then(|text| field.kind.write(row, field.offset, text.trim()));
+            if written.is_none() {
+                let value = String::from_utf8_lossy(value);
+                return Err(match fieldnames.and_then(|f| f.get(field.column)) {
+                    Some(name) => conversion_error(field.kind.dtype(), r.position(), name, &value)?,
+                    None => {
+                        conversion_error(field.kind.dtype(), r.position(), field.column, &value)?
+                    }
+                });
+            }
+        }
+    }
+
+    let array = match array {
+        Some(array) => array.finish()?,
+        None => numpy.call1("empty", (vec![0, 0], dtype))?,
+    };
+    Ok(array.to_object(py))
+}
+
+/// Adds the functions of this module to the `_rustcsv` module.
+pub fn register(m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_function!(read_numpy))
+}
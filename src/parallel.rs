@@ -0,0 +1,430 @@
+//! Parsing a CSV file in chunks on multiple threads.
+//!
+//! The file is split into chunks at record boundaries, which are found by
+//! following the CSV parser, with the rules of [ScanDialect], from every
+//! state it could be in at the start of the chunk, until all of them agree
+//! on where the next record starts. If they don't, e.g. in a file without
+//! any quotes, the next record is assumed to start after the next terminator
+//! outside of quotes.
+//!
+//! Every chunk is checked to end at a record boundary once it has been
+//! parsed. If a chunk doesn't, or fails to parse, it's read sequentially
+//! from its start instead, until the sequential reader reaches the start of
+//! a later chunk, from which on the parsed chunks are used again.
+extern crate csv;
+extern crate pyo3;
+
+use missing::{ScanDialect, ScanState};
+use pyo3::Python;
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::vec;
+
+/// Upper bound of the size of a chunk, in bytes.
+const MAX_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Number of bytes from the start of a chunk searched for a record boundary.
+const SPLIT_WINDOW: u64 = 64 * 1024;
+
+/// Number of parsed chunks each worker thread may read ahead.
+const CHANNEL_CAPACITY: usize = 2;
+
+/// Every state the parser could be in at an arbitrary byte of the file.
+const ALL_STATES: [ScanState; 7] = [
+    ScanState::StartRecord,
+    ScanState::StartField,
+    ScanState::InField,
+    ScanState::InQuotedField,
+    ScanState::InEscapedQuote,
+    ScanState::InDoubleEscapedQuote,
+    ScanState::InComment,
+];
+
+/// Index of [ScanState::InField] in [ALL_STATES], used to guess the record
+/// boundary if the states don't agree.
+const GUESS_STATE: usize = 2;
+
+/// Returns the offset of the first record that starts in `window`.
+fn find_split(dialect: &ScanDialect, window: &[u8]) -> Option<usize> {
+    let mut states = ALL_STATES;
+    let mut guess = None;
+    for (i, &b) in window.iter().enumerate() {
+        for state in states.iter_mut() {
+            *state = dialect.next_state(*state, b);
+        }
+        if states.iter().all(|state| state.is_record_start()) {
+            return Some(i + 1);
+        }
+        if guess.is_none() && states[GUESS_STATE].is_record_start() {
+            guess = Some(i + 1);
+        }
+    }
+    guess
+}
+
+/// What the worker threads share.
+struct Config {
+    path: String,
+    builder: csv::ReaderBuilder,
+    dialect: ScanDialect,
+    /// Length of the first record of the file, which every record must
+    /// match unless `flexible` is set.
+    first_len: usize,
+    flexible: bool,
+    /// Offset of the first record after the header.
+    data_start: u64,
+    len: u64,
+    chunk_size: u64,
+    chunks: usize,
+}
+
+impl Config {
+    /// Returns the offset of the first record that starts in chunk `k`, if
+    /// any.
+    fn split(&self, file: &mut File, k: usize) -> io::Result<Option<u64>> {
+        if k == 0 {
+            return Ok(Some(self.data_start));
+        }
+        let offset = self.data_start + k as u64 * self.chunk_size;
+        let end = cmp::min(offset + cmp::min(SPLIT_WINDOW, self.chunk_size), self.len);
+        let mut window = vec![0; (end - offset) as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut window)?;
+        Ok(find_split(&self.dialect, &window).map(|i| offset + i as u64))
+    }
+
+    /// Reads and parses chunk `k`.
+    fn read_chunk(&self, file: &mut File, k: usize) -> Chunk {
+        match self.try_read_chunk(file, k) {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                debug!("Failed to read chunk {}: {:?}", k, error);
+                Chunk::Failed
+            }
+        }
+    }
+
+    fn try_read_chunk(&self, file: &mut File, k: usize) -> csv::Result<Chunk> {
+        let start = match self.split(file, k)? {
+            Some(start) => start,
+            None => return Ok(Chunk::Merged),
+        };
+        let mut end = self.len;
+        for next in k + 1..self.chunks {
+            if let Some(split) = self.split(file, next)? {
+                end = split;
+                break;
+            }
+        }
+
+        let mut buf = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buf)?;
+        if end < self.len {
+            let state = buf.iter().fold(ScanState::StartRecord, |state, &b| {
+                self.dialect.next_state(state, b)
+            });
+            if !state.is_record_start() {
+                debug!("Chunk {} does not end at a record boundary", k);
+                return Ok(Chunk::Failed);
+            }
+        }
+
+        let mut rdr = self.builder.from_reader(buf.as_slice());
+        let mut records = Vec::new();
+        let mut record = csv::ByteRecord::new();
+        while rdr.read_byte_record(&mut record)? {
+            records.push(record.clone());
+        }
+        // Each chunk only checks the lengths of its records against its own
+        // first record.
+        let first_len = records.first().map_or(self.first_len, |r| r.len());
+        if !self.flexible && first_len != self.first_len {
+            debug!("Chunk {} has records of unequal lengths", k);
+            return Ok(Chunk::Failed);
+        }
+        Ok(Chunk::Parsed(ParsedChunk {
+            start,
+            end,
+            records,
+            end_pos: rdr.position().clone(),
+        }))
+    }
+}
+
+/// The records of a chunk, with positions relative to the start of the
+/// chunk.
+struct ParsedChunk {
+    start: u64,
+    end: u64,
+    records: Vec<csv::ByteRecord>,
+    end_pos: csv::Position,
+}
+
+/// A chunk of the file, as parsed by a worker thread.
+enum Chunk {
+    Parsed(ParsedChunk),
+    /// No record starts in the chunk, it's part of a previous chunk.
+    Merged,
+    /// The chunk could not be parsed on its own.
+    Failed,
+}
+
+/// Parses the chunks `worker`, `worker + threads`, `worker + 2 * threads`,
+/// etc., sending them over `sender` in order.
+fn run_worker(config: Arc<Config>, worker: usize, threads: usize, sender: mpsc::SyncSender<Chunk>) {
+    let mut file = File::open(&config.path);
+    if let Err(ref error) = file {
+        debug!("Worker {} failed to open file: {:?}", worker, error);
+    }
+    for k in (worker..config.chunks).step_by(threads) {
+        let chunk = match file {
+            Ok(ref mut file) => config.read_chunk(file, k),
+            Err(_) => Chunk::Failed,
+        };
+        if sender.send(chunk).is_err() {
+            debug!("Receiver dropped, stopping worker {}", worker);
+            return;
+        }
+    }
+}
+
+/// An [Iterator] over the records of a CSV file, which are parsed in chunks
+/// by multiple threads and returned in order.
+pub struct ParallelRecords {
+    config: Arc<Config>,
+    /// The channel of each worker thread, chunk `k` is received from
+    /// `receivers[k % receivers.len()]`. Always `Some`, except while waiting
+    /// for a chunk.
+    receivers: Vec<Option<mpsc::Receiver<Chunk>>>,
+    next_chunk: usize,
+    /// The records of the current chunk.
+    records: vec::IntoIter<csv::ByteRecord>,
+    /// Position of the start of the current chunk.
+    offset: csv::Position,
+    /// Position of the start of the next chunk.
+    base: csv::Position,
+    /// Set while reading sequentially, after a chunk failed.
+    sequential: Option<csv::Reader<File>>,
+    /// While reading sequentially, the next parsed chunk, which is used once
+    /// the sequential reader reaches its start.
+    pending: Option<ParsedChunk>,
+}
+
+impl ParallelRecords {
+    /// Spawns `threads` threads that parse the file at `path`.
+    ///
+    /// The header is read before returning if `dialect.has_headers` is set,
+    /// `builder` must match `dialect` and `flexible`.
+    pub fn open(
+        path: String,
+        mut builder: csv::ReaderBuilder,
+        dialect: ScanDialect,
+        flexible: bool,
+        threads: usize,
+    ) -> csv::Result<(Option<csv::ByteRecord>, ParallelRecords)> {
+        let mut rdr = builder.has_headers(dialect.has_headers).from_path(&path)?;
+        // Without a header, this reads the first record.
+        let first = rdr.byte_headers()?.clone();
+        let (headers, base) = if dialect.has_headers {
+            (Some(first.clone()), rdr.position().clone())
+        } else {
+            (None, csv::Position::new())
+        };
+        let len = rdr.into_inner().metadata()?.len();
+        builder.has_headers(false);
+
+        let data_len = len.saturating_sub(base.byte());
+        let chunk_size = cmp::max(
+            cmp::min(
+                (data_len + threads as u64 - 1) / threads as u64,
+                MAX_CHUNK_SIZE,
+            ),
+            1,
+        );
+        let config = Arc::new(Config {
+            path,
+            builder,
+            dialect,
+            first_len: first.len(),
+            flexible,
+            data_start: base.byte(),
+            len,
+            chunk_size,
+            chunks: ((data_len + chunk_size - 1) / chunk_size) as usize,
+        });
+        let receivers = (0..threads)
+            .map(|worker| {
+                let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+                let config = config.clone();
+                thread::spawn(move || run_worker(config, worker, threads, sender));
+                Some(receiver)
+            })
+            .collect();
+        Ok((
+            headers,
+            ParallelRecords {
+                config,
+                receivers,
+                next_chunk: 0,
+                records: Vec::new().into_iter(),
+                offset: base.clone(),
+                base,
+                sequential: None,
+                pending: None,
+            },
+        ))
+    }
+
+    /// Waits for the next chunk, releasing the GIL. Returns `None` once all
+    /// chunks have been received, and an error if a worker thread stopped
+    /// before sending all of its chunks.
+    fn next_chunk(&mut self) -> Option<csv::Result<Chunk>> {
+        if self.receivers.is_empty() || self.next_chunk >= self.config.chunks {
+            return None;
+        }
+        let i = self.next_chunk % self.receivers.len();
+        self.next_chunk += 1;
+        let receiver = self.receivers[i].take()?;
+        let gil = Python::acquire_gil();
+        let (receiver, chunk) = gil.python().allow_threads(move || {
+            let chunk = receiver.recv();
+            (receiver, chunk)
+        });
+        self.receivers[i] = Some(receiver);
+        match chunk {
+            Ok(chunk) => Some(Ok(chunk)),
+            Err(_) => {
+                self.receivers.clear();
+                Some(Err(csv::Error::from(io::Error::new(
+                    io::ErrorKind::Other,
+                    "a worker thread stopped before parsing all chunks",
+                ))))
+            }
+        }
+    }
+
+    /// Starts returning the records of `chunk`, which starts at
+    /// [ParallelRecords::base].
+    fn use_chunk(&mut self, chunk: ParsedChunk) {
+        self.records = chunk.records.into_iter();
+        self.offset = self.base.clone();
+        self.base
+            .set_byte(chunk.end)
+            .set_line(self.offset.line() + chunk.end_pos.line() - 1)
+            .set_record(self.offset.record() + chunk.end_pos.record());
+    }
+
+    /// Reads the file sequentially from the start of the next chunk.
+    fn start_sequential(&mut self) -> csv::Result<()> {
+        debug!("Reading sequentially from {:?}", self.base);
+        let mut rdr = self.config.builder.from_path(&self.config.path)?;
+        // The reader checks the lengths of all records against the first
+        // one it reads, which must be the first record of the file rather
+        // than the first one at `base`.
+        let first_len = rdr.byte_headers()?.len();
+        if !self.config.flexible && first_len != self.config.first_len {
+            return Err(csv::Error::from(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the first record of the file changed while reading it",
+            )));
+        }
+        rdr.seek(self.base.clone())?;
+        self.sequential = Some(rdr);
+        Ok(())
+    }
+
+    /// Goes back to the parsed chunks if the sequential reader is at the
+    /// start of one, returning whether it did. Chunks the sequential reader
+    /// has passed did not start at a record boundary, and are dropped.
+    fn try_resume(&mut self) -> csv::Result<bool> {
+        let pos = match self.sequential {
+            Some(ref rdr) => rdr.position().clone(),
+            None => return Ok(false),
+        };
+        loop {
+            let chunk = match self.pending.take() {
+                Some(chunk) => chunk,
+                None => match self.next_chunk() {
+                    Some(Ok(Chunk::Parsed(chunk))) => chunk,
+                    Some(Ok(_)) => continue,
+                    Some(Err(error)) => return Err(error),
+                    None => return Ok(false),
+                },
+            };
+            if chunk.start > pos.byte() {
+                self.pending = Some(chunk);
+                return Ok(false);
+            }
+            if chunk.start == pos.byte() {
+                debug!("Resuming parallel parsing at {:?}", pos);
+                self.sequential = None;
+                self.base = pos;
+                self.use_chunk(chunk);
+                return Ok(true);
+            }
+            debug!("Dropping chunk starting at byte {}", chunk.start);
+        }
+    }
+
+    fn read_sequential(&mut self) -> Option<csv::Result<csv::ByteRecord>> {
+        let rdr = self.sequential.as_mut()?;
+        let mut record = csv::ByteRecord::new();
+        match rdr.read_byte_record(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// Makes the position of `record` relative to the start of the file.
+    fn adjust_position(&self, mut record: csv::ByteRecord) -> csv::ByteRecord {
+        let pos = record.position().map(|pos| {
+            let mut adjusted = csv::Position::new();
+            adjusted
+                .set_byte(self.offset.byte() + pos.byte())
+                .set_line(self.offset.line() + pos.line() - 1)
+                .set_record(self.offset.record() + pos.record());
+            adjusted
+        });
+        record.set_position(pos);
+        record
+    }
+}
+
+impl Iterator for ParallelRecords {
+    type Item = csv::Result<csv::ByteRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.records.next() {
+                return Some(Ok(self.adjust_position(record)));
+            }
+            if self.sequential.is_some() {
+                match self.try_resume() {
+                    Ok(true) => continue,
+                    Ok(false) => return self.read_sequential(),
+                    Err(error) => {
+                        self.sequential = None;
+                        return Some(Err(error));
+                    }
+                }
+            }
+            match self.next_chunk()? {
+                Ok(Chunk::Parsed(chunk)) => self.use_chunk(chunk),
+                Ok(Chunk::Merged) => (),
+                Ok(Chunk::Failed) => {
+                    if let Err(error) = self.start_sequential() {
+                        self.receivers.clear();
+                        return Some(Err(error));
+                    }
+                }
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
@@ -1,15 +1,23 @@
 extern crate pyo3;
 
 use pyo3::exceptions as exc;
+use pyo3::ffi;
 use pyo3::prelude::*;
 use pyo3::types::PyBytes;
 use pyo3::types::PyObjectRef;
 use pyo3::types::PyString;
 use pyo3::FromPyObject;
+use pyo3::PyTryFrom;
 use pyo3::Python;
+use std::fs::File;
 use std::io;
 use std::io::Read;
+use std::io::Seek;
 use std::io::Write;
+use std::mem::ManuallyDrop;
+use std::os::raw::c_char;
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
 
 /// Wraps a "[binary file]" [`PyObject`].
 ///
@@ -20,6 +28,137 @@ use std::io::Write;
 #[derive(Debug)]
 pub struct PyFile {
     file_like: PyObject,
+    /// The `readinto` method of [PyFile::file_like], if it has one.
+    readinto: Option<PyObject>,
+    /// Reads from the file descriptor of [PyFile::file_like], if it has one.
+    ///
+    /// `None` until the first read, so that file descriptors are only used
+    /// when reading.
+    fd_reader: Option<Option<FdReader>>,
+}
+
+/// Reads directly from the file descriptor of a Python file object, without
+/// going through Python.
+#[derive(Debug)]
+struct FdReader {
+    /// Bytes read ahead and buffered by the Python file object, these are
+    /// read before reading from the file descriptor.
+    buffered: io::Cursor<Vec<u8>>,
+    /// The file descriptor is owned by the Python file object, so it must
+    /// not be closed when the [FdReader] is dropped.
+    file: ManuallyDrop<File>,
+}
+
+impl FdReader {
+    /// Creates an [FdReader] if `file_like` has a readable file descriptor,
+    /// see [reads_file_descriptor].
+    ///
+    /// The file descriptor is positioned at the current position of
+    /// `file_like`:
+    ///
+    /// - If `file_like` is seekable, by seeking the file descriptor to
+    ///   `file_like.tell()`.
+    /// - Otherwise, if `file_like` is buffered, by taking over the bytes
+    ///   buffered by `file_like`.
+    #[cfg(unix)]
+    fn from_object(py: Python, file_like: &PyObject) -> PyResult<Option<FdReader>> {
+        if !reads_file_descriptor(file_like.as_ref(py)) {
+            return Ok(None);
+        }
+        let fd: i32 = match file_like
+            .call_method0(py, "fileno")
+            .and_then(|fd| fd.extract(py))
+        {
+            Ok(fd) => fd,
+            // e.g. io.BytesIO raises io.UnsupportedOperation
+            Err(_) => return Ok(None),
+        };
+        if !call_bool_method(py, file_like, "readable") {
+            return Ok(None);
+        }
+
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+        let mut buffered = Vec::new();
+        if call_bool_method(py, file_like, "seekable") {
+            let pos: u64 = file_like.call_method0(py, "tell")?.extract(py)?;
+            file.seek(io::SeekFrom::Start(pos))?;
+        } else if let Ok(peek) = file_like.getattr(py, "peek") {
+            // Returns the buffered bytes, only reading from the file
+            // descriptor if nothing is buffered.
+            let peeked = peek.call1(py, (1,))?;
+            let size = <PyBytes as PyTryFrom>::try_from(peeked.as_ref(py))?
+                .as_bytes()
+                .len();
+            let read_result = file_like.call_method1(py, "read", (size,))?;
+            buffered.extend_from_slice(
+                <PyBytes as PyTryFrom>::try_from(read_result.as_ref(py))?.as_bytes(),
+            );
+        }
+        debug!(
+            "Reading from file descriptor {} of {:?}",
+            fd,
+            file_like.as_ref(py)
+        );
+        Ok(Some(FdReader {
+            buffered: io::Cursor::new(buffered),
+            file,
+        }))
+    }
+
+    #[cfg(not(unix))]
+    fn from_object(_py: Python, _file_like: &PyObject) -> PyResult<Option<FdReader>> {
+        Ok(None)
+    }
+}
+
+impl Read for FdReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.buffered.read(buf)?;
+        if n > 0 {
+            return Ok(n);
+        }
+        self.file.read(buf)
+    }
+}
+
+impl Seek for FdReader {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        // The file descriptor is ahead of the current position by the bytes
+        // left in the buffer.
+        let remaining = self.buffered.get_ref().len() as u64 - self.buffered.position();
+        let pos = match pos {
+            io::SeekFrom::Current(offset) => {
+                let file_pos = self.file.seek(io::SeekFrom::Current(0))?;
+                io::SeekFrom::Start(((file_pos - remaining) as i64 + offset) as u64)
+            }
+            pos => pos,
+        };
+        self.buffered = io::Cursor::new(Vec::new());
+        self.file.seek(pos)
+    }
+}
+
+/// Whether `file_like` reads the bytes of its file descriptor as-is, i.e. is
+/// an `io.FileIO` or an `io.BufferedReader` over one.
+///
+/// Other file objects with a `fileno()`, e.g. `gzip.GzipFile`, may read
+/// something else than the bytes of the file descriptor. Subclasses may
+/// override `read`, so only the exact types are accepted.
+#[cfg(unix)]
+fn reads_file_descriptor(file_like: &PyObjectRef) -> bool {
+    let is_type = |obj: &PyObjectRef, name: &str| obj.get_type().name() == name;
+    is_type(file_like, "_io.FileIO")
+        || (is_type(file_like, "_io.BufferedReader")
+            && file_like
+                .getattr("raw")
+                .map_or(false, |raw| is_type(raw, "_io.FileIO")))
+}
+
+/// Calls a method returning a `bool`, returning `false` if the call fails.
+fn call_bool_method(py: Python, obj: &PyObject, name: &str) -> bool {
+    obj.call_method0(py, name)
+        .and_then(|result| result.extract(py))
+        .unwrap_or(false)
 }
 
 impl PyFile {
@@ -29,6 +168,11 @@ impl PyFile {
     ///
     /// * `file_like` - [binary file] PyObject, will be quack-tested by getting
     /// the `read` Python attribute from it.
+    ///
+    /// If `file_like` is a file opened with `open(path, "rb")`, or an
+    /// unbuffered `io.FileIO`, reads will bypass Python
+    /// and read from the file descriptor directly. The position of
+    /// `file_like` is undefined after reading from it.
     pub fn from_object(file_like: PyObject) -> PyResult<PyFile> {
         let gil = Python::acquire_gil();
         let py = gil.python();
@@ -38,7 +182,14 @@ impl PyFile {
         // https://docs.python.org/3/library/io.html#io.IOBase.readable
 
         match file_like.getattr(py, "read") {
-            Ok(_) => Ok(PyFile { file_like }),
+            Ok(_) => {
+                let readinto = file_like.getattr(py, "readinto").ok();
+                Ok(PyFile {
+                    file_like,
+                    readinto,
+                    fd_reader: None,
+                })
+            }
             Err(error) => Err(exc::TypeError::py_err(format!(
                 "Expected a file-like object, got {:?} (original error: {:?})",
                 file_like.as_ref(py),
@@ -47,40 +198,107 @@ impl PyFile {
         }
     }
 
-    /// Reads bytes from the the [binary file] [PyObject] [PyFile::file_like]
-    ///
-    /// The method acquires the GIL, then calls `getattr(file_like,
+    /// Makes reads and seeks call the methods of [PyFile::file_like] rather
+    /// than using its file descriptor, so that its position, including
+    /// that of its buffer, stays consistent.
+    pub fn without_fd(mut self) -> PyFile {
+        self.fd_reader = Some(None);
+        self
+    }
+
+    /// Whether [PyFile::file_like] is seekable, according to its `seekable`
+    /// method.
+    pub fn seekable(&self) -> bool {
+        let gil = Python::acquire_gil();
+        call_bool_method(gil.python(), &self.file_like, "seekable")
+    }
+
+    /// Returns the current offset of [PyFile::file_like] if it's seekable,
+    /// or `None` if it's not, or telling its offset fails.
+    pub fn seekable_offset(&mut self) -> Option<u64> {
+        if !self.seekable() {
+            return None;
+        }
+        self.seek(io::SeekFrom::Current(0)).ok()
+    }
+
+    /// Reads bytes from the [binary file] [PyObject] [PyFile::file_like]
+    /// directly into `buf`.
     ///
-    /// # Arguments
+    /// If the file has a `readinto` method it's called with a `memoryview`
+    /// over `buf`, so that the bytes are written straight into `buf` without
+    /// any intermediate allocations. Otherwise `read` is called, and the
+    /// returned `bytes` are copied into `buf`.
     ///
-    /// - `size` - Maximum number of bytes to read.
-    #[inline]
-    pub fn read_bytes(&self, size: usize) -> PyResult<Box<Vec<u8>>> {
+    /// Returns the number of bytes read.
+    pub fn read_into(&self, buf: &mut [u8]) -> PyResult<usize> {
         let gil = Python::acquire_gil();
         let py = gil.python();
 
-        // Get the fd.read() callable
-        let read_func: PyObject = self.file_like.getattr(py, "read")?;
-        //        debug!("read_func is {:?}", read_func.as_ref(py));
-
-        // Call fd.read(size)
-        let call_result = read_func.call1(py, (size,))?;
-
-        // Extract the PyBytes into a Box<Vec<u8>>
-        match call_result.extract(py) {
-            Ok(r) => Ok(Box::new(r)),
-            //
-            Err(error) => {
-                if py.is_instance::<PyString, _>(call_result.as_ref(py))? {
-                    return Err(exc::TypeError::py_err(format!(
-                        "The file {:?} is not open in binary mode. (Cause: {:?})",
-                        self.file_like.as_ref(py),
-                        error.to_object(py).as_ref(py),
+        let readinto = match self.readinto {
+            Some(ref readinto) => readinto,
+            None => {
+                let call_result = self.file_like.call_method1(py, "read", (buf.len(),))?;
+                let bytes: &PyBytes = match <PyBytes as PyTryFrom>::try_from(call_result.as_ref(py))
+                {
+                    Ok(bytes) => bytes,
+                    Err(_) => return Err(self.not_binary_error(py, call_result.as_ref(py))?),
+                };
+                let data = bytes.as_bytes();
+                if data.len() > buf.len() {
+                    return Err(exc::IOError::py_err(format!(
+                        "read({}) returned {} bytes",
+                        buf.len(),
+                        data.len()
                     )));
-                } else {
-                    return Err(error);
                 }
+                buf[..data.len()].copy_from_slice(data);
+                return Ok(data.len());
             }
+        };
+
+        let memoryview = unsafe {
+            PyObject::from_owned_ptr_or_err(
+                py,
+                ffi::PyMemoryView_FromMemory(
+                    buf.as_mut_ptr() as *mut c_char,
+                    buf.len() as ffi::Py_ssize_t,
+                    ffi::PyBUF_WRITE,
+                ),
+            )?
+        };
+        let call_result = readinto.call1(py, (&memoryview,));
+        // The memoryview must not be used after `buf` goes out of scope.
+        memoryview.call_method0(py, "release")?;
+
+        match call_result?.extract::<Option<usize>>(py)? {
+            Some(n) if n > buf.len() => Err(exc::IOError::py_err(format!(
+                "readinto() of a {} byte buffer returned {}",
+                buf.len(),
+                n
+            ))),
+            Some(n) => Ok(n),
+            None => Err(exc::IOError::py_err(format!(
+                "{:?} has no data available, non-blocking reads are not supported",
+                self.file_like.as_ref(py)
+            ))),
+        }
+    }
+
+    /// Creates the error raised when [PyFile::file_like] returns something
+    /// other than `bytes`.
+    fn not_binary_error(&self, py: Python, result: &PyObjectRef) -> PyResult<PyErr> {
+        if py.is_instance::<PyString, _>(result)? {
+            Ok(exc::TypeError::py_err(format!(
+                "The file {:?} is not open in binary mode.",
+                self.file_like.as_ref(py),
+            )))
+        } else {
+            Ok(exc::TypeError::py_err(format!(
+                "Expected bytes from {:?}, got {:?}",
+                self.file_like.as_ref(py),
+                result,
+            )))
         }
     }
 
@@ -123,14 +341,29 @@ impl Write for PyFile {
 }
 
 impl Read for PyFile {
-    /// Reads bytes from the [`PyFile.file_like`] [`PyObject`] via
-    /// [`PyFile.read_bytes`].
+    /// Reads bytes from the file descriptor of [`PyFile.file_like`], if it
+    /// has one, otherwise from the [`PyFile.file_like`] [`PyObject`] via
+    /// [`PyFile.read_into`].
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         debug!("buf.len(): {:?}", buf.len());
-        match self.read_bytes(buf.len()) {
-            // Write the bytes into "buf"
-            // Need to borrow as mutable here, not sure why
-            Ok(read_buf) => buf.as_mut().write(&read_buf[..]),
+        if self.fd_reader.is_none() {
+            let gil = Python::acquire_gil();
+            let py = gil.python();
+            // Text files have no "readinto", and are left to fail in
+            // PyFile::read_into.
+            self.fd_reader = Some(match self.readinto {
+                Some(_) => FdReader::from_object(py, &self.file_like)?,
+                None => None,
+            });
+        }
+        if let Some(Some(ref mut fd_reader)) = self.fd_reader {
+            // Release the GIL while reading, the read may block e.g. on a
+            // pipe written to by another Python thread.
+            let gil = Python::acquire_gil();
+            return gil.python().allow_threads(move || fd_reader.read(buf));
+        }
+        match self.read_into(buf) {
+            Ok(n) => Ok(n),
             Err(error) => {
                 let gil = Python::acquire_gil();
                 let py = gil.python();
@@ -152,6 +385,38 @@ impl Read for PyFile {
     }
 }
 
+impl Seek for PyFile {
+    /// Seeks the file descriptor of [`PyFile.file_like`] if reads bypass
+    /// Python, otherwise calls the `seek` method of [`PyFile.file_like`].
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        if let Some(Some(ref mut fd_reader)) = self.fd_reader {
+            return fd_reader.seek(pos);
+        }
+        let (offset, whence) = match pos {
+            io::SeekFrom::Start(offset) => (offset as i64, 0),
+            io::SeekFrom::Current(offset) => (offset, 1),
+            io::SeekFrom::End(offset) => (offset, 2),
+        };
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        match self
+            .file_like
+            .call_method1(py, "seek", (offset, whence))
+            .and_then(|pos| pos.extract(py))
+        {
+            Ok(pos) => Ok(pos),
+            Err(error) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "Could not seek {:?}: {:?}",
+                    self.file_like.as_ref(py),
+                    error.to_object(py).as_ref(py),
+                ),
+            )),
+        }
+    }
+}
+
 impl Drop for PyFile {
     fn drop(&mut self) {
         debug!("Dropping {:?}", self);
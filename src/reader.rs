@@ -1,9 +1,21 @@
+extern crate encoding_rs;
+extern crate encoding_rs_io;
 extern crate pyo3;
+use compression::Compression;
+use convert::ColumnTypes;
+use dialect::Dialect;
+use encoding_rs::{DecoderResult, Encoding};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use missing::{NaValues, QuoteScannerConfig, ScanDialect, ScanState};
+use mmap::MappedFile;
+use parallel::ParallelRecords;
 use py_file::PyFile;
 use pyo3::class::PyIterProtocol;
 use pyo3::exceptions as exc;
 use pyo3::prelude::*;
 use pyo3::types::PyBytes;
+use pyo3::types::PyDict;
+use pyo3::types::PyList;
 use pyo3::types::PyObjectRef;
 use pyo3::types::PyString;
 use pyo3::PyObject;
@@ -11,9 +23,29 @@ use pyo3::PyRawObject;
 use pyo3::PyResult;
 use pyo3::Python;
 use record;
-use util::get_optional_single_byte;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::mem;
+use threaded::ThreadedIter;
 
-type RecordsIter = Iterator<Item = csv::Result<csv::StringRecord>>;
+type RecordsIter = Iterator<Item = csv::Result<csv::ByteRecord>>;
+
+/// A source that can be both read and seeked.
+pub trait ReadSeek: io::Read + io::Seek {}
+
+impl<T: io::Read + io::Seek> ReadSeek for T {}
+
+/// Line numbers from this one up are unknown, and are returned as `None`.
+///
+/// Seeking to a position without a line, e.g. from `rustcsv.index_position`,
+/// continues counting lines from here, so that the lines of later positions
+/// are unknown too.
+pub const UNKNOWN_LINE: u64 = 1 << 63;
+
+/// Default `batch_size` if `columnar` is set.
+const DEFAULT_COLUMNAR_BATCH_SIZE: usize = 65536;
 
 /// Handles [CSVReader]'s reading from either a filesystem path or "`BinaryIO`" [PyObject]
 pub enum CSVSource {
@@ -21,66 +53,574 @@ pub enum CSVSource {
     Path(String),
     /// A [PyFile] wrapping a Python file-like "`BinaryIO`" [PyObject].
     Readable(PyFile),
+    /// A memory-mapped file.
+    Mapped(MappedFile),
+}
+
+impl CSVSource {
+    /// Treats `path_or_fd` as a path if it's a `str`, otherwise as a
+    /// "binary file".
+    pub fn from_object(py: Python, path_or_fd: PyObject) -> PyResult<CSVSource> {
+        if py.is_instance::<PyString, _>(path_or_fd.as_ref(py))? {
+            Ok(CSVSource::Path(path_or_fd.extract(py)?))
+        } else {
+            Ok(CSVSource::Readable(PyFile::from_object(path_or_fd)?))
+        }
+    }
+
+    /// Resolves [Compression::Infer] from the extension or magic number of a
+    /// path, or the magic number of a seekable file. Files that are not
+    /// seekable stay [Compression::Infer], which is resolved while reading.
+    ///
+    /// [Compression::None] doesn't mean the source can seek, that's up to
+    /// [CSVSource::seekable_offset].
+    pub fn detect_compression(&mut self, compression: Compression) -> io::Result<Compression> {
+        if compression != Compression::Infer {
+            return Ok(compression);
+        }
+        match *self {
+            CSVSource::Path(ref path) => match Compression::from_extension(path) {
+                Compression::Infer => compression.detect(&mut File::open(path)?),
+                compression => Ok(compression),
+            },
+            // Reading the magic number only works if the file can seek back
+            // to where it was.
+            CSVSource::Readable(ref mut readable) if readable.seekable_offset().is_some() => {
+                compression.detect(readable)
+            }
+            CSVSource::Readable(_) => Ok(compression),
+            CSVSource::Mapped(ref mapped) => compression.detect(&mut io::Cursor::new(mapped)),
+        }
+    }
+
+    /// Returns the current offset of the source if it can seek, or `None` if
+    /// it's not seekable or telling its offset fails, e.g. for a pipe.
+    pub fn seekable_offset(&mut self) -> Option<u64> {
+        match *self {
+            CSVSource::Readable(ref mut readable) => readable.seekable_offset(),
+            CSVSource::Path(_) | CSVSource::Mapped(_) => Some(0),
+        }
+    }
+
+    /// Memory-maps a path, raising a `ValueError` for other sources.
+    pub fn into_mapped(self, py: Python) -> PyResult<CSVSource> {
+        match self {
+            CSVSource::Path(path) => Ok(CSVSource::Mapped(MappedFile::open(py, &path)?)),
+            CSVSource::Readable(_) => Err(exc::ValueError::py_err(
+                "mmap is only supported when reading from a path",
+            )),
+            source => Ok(source),
+        }
+    }
+
+    /// Opens the source for reading, decompressing it according to
+    /// `compression`.
+    pub fn into_reader(self, compression: Compression) -> io::Result<Box<io::Read + Send>> {
+        let (readable, compression): (Box<io::Read + Send>, _) = match self {
+            CSVSource::Path(path) => {
+                let compression = match compression {
+                    Compression::Infer => Compression::from_extension(&path),
+                    compression => compression,
+                };
+                (Box::new(File::open(path)?), compression)
+            }
+            CSVSource::Readable(readable) => (Box::new(readable), compression),
+            CSVSource::Mapped(mapped) => (Box::new(io::Cursor::new(mapped)), compression),
+        };
+        compression.decoder(readable)
+    }
+
+    /// Opens the source for reading and seeking.
+    pub fn into_seekable_reader(self) -> io::Result<Box<ReadSeek + Send>> {
+        match self {
+            CSVSource::Path(path) => Ok(Box::new(File::open(path)?)),
+            CSVSource::Readable(readable) => Ok(Box::new(readable)),
+            CSVSource::Mapped(mapped) => Ok(Box::new(io::Cursor::new(mapped))),
+        }
+    }
+}
+
+/// The records of a [CSVReader].
+enum Records {
+    /// Records read through [make_records_iterator] or [ParallelRecords].
+    Iter(Box<RecordsIter>),
+    /// Records read straight from the source, which supports seeking.
+    Seekable(SeekableRecords),
+}
+
+impl Iterator for Records {
+    type Item = csv::Result<csv::ByteRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match *self {
+            Records::Iter(ref mut iter) => iter.next(),
+            Records::Seekable(ref mut records) => records.next(),
+        }
+    }
+}
+
+/// An [Iterator] over the records of a [csv::Reader] that can seek.
+pub struct SeekableRecords {
+    rdr: csv::Reader<Box<ReadSeek + Send>>,
+    /// Offset of the source when it was opened, positions are relative to
+    /// it.
+    start: u64,
+}
+
+impl SeekableRecords {
+    /// Like [make_records_iterator], but without any transcoding or
+    /// threading, so that the [csv::Reader] can seek in `source`, which is
+    /// at offset `start`, as returned by [CSVSource::seekable_offset].
+    pub fn new(
+        source: CSVSource,
+        start: u64,
+        builder: &csv::ReaderBuilder,
+        has_headers: bool,
+    ) -> csv::Result<(Option<csv::ByteRecord>, SeekableRecords)> {
+        let mut rdr = builder.from_reader(source.into_seekable_reader()?);
+        let headers = if has_headers {
+            Some(rdr.byte_headers()?.clone())
+        } else {
+            None
+        };
+        Ok((headers, SeekableRecords { rdr, start }))
+    }
+
+    /// Seeks to `pos`, the next record read is the record at `pos`.
+    pub fn seek(&mut self, pos: csv::Position) -> csv::Result<()> {
+        let offset = self.start + pos.byte();
+        self.rdr.seek_raw(io::SeekFrom::Start(offset), pos)
+    }
+}
+
+impl Iterator for SeekableRecords {
+    type Item = csv::Result<csv::ByteRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = csv::ByteRecord::new();
+        match self.rdr.read_byte_record(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
 }
 
 // Python docstring for CSVReader
-/// CSVReader(path_or_fd, delimiter, terminator)
+/// CSVReader(path_or_fd, delimiter, terminator, has_headers, fieldnames, quotechar, escapechar, doublequote, quoting, comment, trim, flexible, bytes_mode, encoding, errors, batch_size, threaded, dtypes, na_values, quoted_na, columnar, skip_lines, with_position, threads, compression, mmap, dialect)
 /// --
 #[pyclass(subclass)]
 pub struct CSVReader {
     // It would be nice to have a reference to csv::Reader here,
     // but I haven't figured out lifetimes yet.
     /// Iterator over the parsed records
-    iter: Box<RecordsIter>,
+    iter: Records,
+    /// Column names, if set the records are returned as `dict`s keyed by
+    /// these names instead of as `tuple`s.
+    fieldnames: Option<Vec<String>>,
+    /// Whether records of unequal lengths are allowed.
+    flexible: bool,
+    /// Return the fields as `bytes` instead of `str`.
+    bytes_mode: bool,
+    /// How fields that are not valid UTF-8 are handled.
+    errors: record::DecodeErrors,
+    /// If set, iterating yields `list`s of up to this many records.
+    batch_size: Option<usize>,
+    /// If set, fields are converted according to the column types.
+    dtypes: Option<ColumnTypes>,
+    /// If set, matching fields are returned as `None`.
+    na_values: Option<NaValues>,
+    /// Return batches as `dict`s of columns instead of `list`s of records.
+    columnar: bool,
+    /// Return `(Position, record)` tuples instead of records.
+    with_position: bool,
+    /// Position of the last record read.
+    position: Option<csv::Position>,
+    /// The memory-mapped file, if `mmap` is set. Fields are returned as
+    /// `memoryview`s of it in `bytes_mode`.
+    mapped: Option<MappedFile>,
 }
 
 /// Builds a [`csv::Reader`] and returns a boxed [`Iterator`] of the
-/// records from [`csv::Reader::into_records`].
+/// records from [`csv::Reader::into_byte_records`].
 ///
 /// # Arguments
 ///
 /// * `source` - [CSVSource] to read the CSV from.
-/// * `delimiter` - CSV field separator.
-/// * `terminator` - CSV record separator.
+/// * `compression` - [Compression] of the source, see
+///   [CSVSource::into_reader].
+/// * `skip_lines` - If set, lines to skip before parsing, see [SkipLines].
+/// * `builder` - [csv::ReaderBuilder] configured with the CSV dialect.
+/// * `has_headers` - Read the first record as the header, must match the
+///   `has_headers` setting of `builder`.
+/// * `transcode` - If set, the source is transcoded to UTF-8 before
+///   parsing, see [Transcode].
+/// * `threaded` - Parse the records in a background thread, see
+///   [ThreadedIter].
+/// * `quote_scanner` - If set, the source is read through a
+///   [missing::QuoteScanner] to find the quoted fields of each record.
+///
+/// Returns the header record, if `has_headers` is set, and the iterator over
+/// the remaining records.
 pub fn make_records_iterator(
     source: CSVSource,
-    delimiter: u8,
-    terminator: u8,
-) -> csv::Result<Box<RecordsIter>> {
-    let mut x = csv::ReaderBuilder::new();
-    let builder = x
-        .delimiter(delimiter)
-        .has_headers(false)
-        .terminator(csv::Terminator::Any(terminator));
+    compression: Compression,
+    skip_lines: Option<SkipLinesConfig>,
+    builder: &csv::ReaderBuilder,
+    has_headers: bool,
+    transcode: Option<Transcode>,
+    threaded: bool,
+    quote_scanner: Option<QuoteScannerConfig>,
+) -> csv::Result<(Option<csv::ByteRecord>, Box<RecordsIter>)> {
+    let mut readable = source.into_reader(compression)?;
+    match transcode {
+        Some(Transcode {
+            encoding,
+            strict: true,
+        }) => readable = Box::new(StrictDecoder::new(readable, encoding)),
+        Some(Transcode {
+            encoding,
+            strict: false,
+        }) => {
+            readable = Box::new(
+                DecodeReaderBytesBuilder::new()
+                    .encoding(Some(encoding))
+                    .bom_override(true)
+                    .strip_bom(true)
+                    .build(readable),
+            )
+        }
+        None => (),
+    }
+    if let Some(skip_lines) = skip_lines {
+        readable = Box::new(skip_lines.wrap(readable));
+    }
+    if let Some(quote_scanner) = quote_scanner {
+        readable = Box::new(quote_scanner.wrap(readable));
+    }
+    into_records(builder.from_reader(readable), has_headers, threaded)
+}
+
+/// How [make_records_iterator] transcodes its source to UTF-8. A BOM, if
+/// present, takes precedence over `encoding` and is stripped.
+pub struct Transcode {
+    pub encoding: &'static Encoding,
+    /// Fail with a [DecodeError] on malformed input, instead of replacing it
+    /// with U+FFFD.
+    pub strict: bool,
+}
+
+/// Malformed input found by [StrictDecoder].
+#[derive(Debug)]
+pub struct DecodeError(String);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for DecodeError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Transcodes `inner` to UTF-8 like [encoding_rs_io::DecodeReaderBytes],
+/// but fails with a [DecodeError] on malformed input.
+struct StrictDecoder<R> {
+    inner: R,
+    decoder: encoding_rs::Decoder,
+    /// Input read from `inner` that is not decoded yet, `buf[start..end]`.
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    /// Whether `inner` is at EOF.
+    eof: bool,
+    /// Whether the decoder has been flushed at EOF.
+    finished: bool,
+}
+
+impl<R: io::Read> StrictDecoder<R> {
+    fn new(inner: R, encoding: &'static Encoding) -> StrictDecoder<R> {
+        StrictDecoder {
+            inner,
+            // Sniffs and strips a BOM.
+            decoder: encoding.new_decoder(),
+            buf: vec![0; 8 * 1024],
+            start: 0,
+            end: 0,
+            eof: false,
+            finished: false,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for StrictDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.finished {
+            if self.start == self.end && !self.eof {
+                self.start = 0;
+                self.end = self.inner.read(&mut self.buf)?;
+                self.eof = self.end == 0;
+            }
+            let (result, read, written) = self.decoder.decode_to_utf8_without_replacement(
+                &self.buf[self.start..self.end],
+                buf,
+                self.eof,
+            );
+            self.start += read;
+            match result {
+                DecoderResult::Malformed(_, _) => {
+                    let message = format!("invalid {} input", self.decoder.encoding().name());
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        DecodeError(message),
+                    ));
+                }
+                DecoderResult::InputEmpty => self.finished = self.eof,
+                DecoderResult::OutputFull if written == 0 => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "buffer too small to decode into",
+                    ))
+                }
+                DecoderResult::OutputFull => (),
+            }
+            if written > 0 {
+                return Ok(written);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// The lines [make_records_iterator] skips before parsing.
+pub struct SkipLinesConfig {
+    pub lines: usize,
+    /// The CSV dialect, lines end at its terminator outside of quotes.
+    pub dialect: ScanDialect,
+}
+
+impl SkipLinesConfig {
+    fn wrap<R: io::Read>(self, inner: R) -> SkipLines<R> {
+        SkipLines {
+            inner,
+            dialect: self.dialect,
+            lines: self.lines,
+            state: ScanState::StartRecord,
+            after_cr: false,
+        }
+    }
+}
+
+/// Skips the first `lines` lines of `inner`. Lines end where the CSV parser
+/// would end a record, so a quoted field may span lines, and empty lines and
+/// comments count as lines too.
+struct SkipLines<R> {
+    inner: R,
+    dialect: ScanDialect,
+    lines: usize,
+    state: ScanState,
+    /// Whether the last line ended at a `\r` of a CRLF terminator, so that a
+    /// `\n` right after it is skipped with it.
+    after_cr: bool,
+}
+
+impl<R> SkipLines<R> {
+    /// Returns the number of bytes at the start of `data` that are skipped.
+    fn skip(&mut self, data: &[u8]) -> usize {
+        for (i, &b) in data.iter().enumerate() {
+            if mem::replace(&mut self.after_cr, false) && b == b'\n' {
+                continue;
+            }
+            if self.lines == 0 {
+                return i;
+            }
+            self.state = self.dialect.next_state(self.state, b);
+            if self.state.is_record_start() {
+                self.lines -= 1;
+                self.after_cr = match self.dialect.terminator {
+                    csv::Terminator::Any(_) => false,
+                    _ => b == b'\r',
+                };
+            }
+        }
+        data.len()
+    }
+}
 
-    use self::CSVSource::{Path, Readable};
-    {
-        match source {
-            Readable(readable) => {
-                let rdr = builder.from_reader(readable);
-                Ok(Box::new(rdr.into_records()))
+impl<R: io::Read> io::Read for SkipLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.lines == 0 && !self.after_cr {
+                return self.inner.read(buf);
+            }
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return Ok(0);
             }
-            Path(path) => {
-                let rdr = builder.from_path(path)?;
-                Ok(Box::new(rdr.into_records()))
+            let start = self.skip(&buf[..n]);
+            if start < n {
+                for i in start..n {
+                    buf[i - start] = buf[i];
+                }
+                return Ok(n - start);
             }
         }
     }
 }
 
+fn into_records<R: io::Read + Send + 'static>(
+    mut rdr: csv::Reader<R>,
+    has_headers: bool,
+    threaded: bool,
+) -> csv::Result<(Option<csv::ByteRecord>, Box<RecordsIter>)> {
+    let headers = if has_headers {
+        Some(rdr.byte_headers()?.clone())
+    } else {
+        None
+    };
+    if threaded {
+        Ok((
+            headers,
+            Box::new(ThreadedIter::spawn(rdr.into_byte_records())),
+        ))
+    } else {
+        Ok((headers, Box::new(rdr.into_byte_records())))
+    }
+}
+
+/// Looks up an [Encoding] by its [WHATWG label], falling back to the
+/// canonical name of the Python codec of the same name, e.g. `"koi8_r"`.
+///
+/// Note that WHATWG maps `"latin-1"` and `"iso-8859-1"` to windows-1252.
+///
+///  [WHATWG label]: https://encoding.spec.whatwg.org/#names-and-labels
+fn parse_encoding(py: Python, label: &str) -> PyResult<&'static Encoding> {
+    if let Some(encoding) = Encoding::for_label(label.as_bytes()) {
+        return Ok(encoding);
+    }
+    let codec_name: String = py
+        .import("codecs")?
+        .call1("lookup", (label,))?
+        .getattr("name")?
+        .extract()?;
+    match Encoding::for_label(codec_name.as_bytes()) {
+        Some(encoding) => Ok(encoding),
+        None => Err(exc::LookupError::py_err(format!(
+            "Unsupported encoding: {:?}",
+            label
+        ))),
+    }
+}
+
+fn parse_decode_errors(py: Python, errors: &str) -> PyResult<record::DecodeErrors> {
+    match errors {
+        "strict" => Ok(record::DecodeErrors::Strict),
+        _ => {
+            // Raises a LookupError if there is no such error handler
+            py.import("codecs")?.call1("lookup_error", (errors,))?;
+            Ok(record::DecodeErrors::Handler(errors.into()))
+        }
+    }
+}
+
+fn parse_trim(trim: &str) -> PyResult<csv::Trim> {
+    match trim {
+        "none" => Ok(csv::Trim::None),
+        "headers" => Ok(csv::Trim::Headers),
+        "fields" => Ok(csv::Trim::Fields),
+        "all" => Ok(csv::Trim::All),
+        _ => Err(exc::ValueError::py_err(format!("Invalid trim: {:?}", trim))),
+    }
+}
+
 /// Implements the Python type methods for `CSVReader`
 #[pymethods]
 impl CSVReader {
-    /// CSVReader(path_or_fd, delimiter: bytes, terminator: bytes)
+    /// CSVReader(path_or_fd, delimiter: bytes, terminator: bytes, has_headers: bool, fieldnames: List[str], quotechar: bytes, escapechar: bytes, doublequote: bool, quoting: bool, comment: bytes, trim: str, flexible: bool, bytes_mode: bool, encoding: str, errors: str, batch_size: int, threaded: bool, dtypes: Dict[Union[int, str], Union[str, type]], na_values: List[str], quoted_na: bool, columnar: bool, skip_lines: int, with_position: bool, threads: int, compression: str, mmap: bool, dialect: Union[Dialect, str, Type[csv.Dialect]])
     /// --
     /// Creates a new CSVReader instance
     ///
     /// - `path_or_fd` - Either a string path to a file or a [binary file].
     /// - `delimiter` - CSV field separator
-    /// - `terminator` - CSV field separator
+    /// - `terminator` - CSV record terminator, either a single byte or
+    ///   `b"\r\n"`, which also accepts a lone `b"\r"` or `b"\n"`.
+    ///   Defaults to `b"\n"`.
+    /// - `has_headers` - Read the first record as the column names.
+    /// - `fieldnames` - Column names to use, the first record is treated as
+    ///   data unless `has_headers` is also set.
+    /// - `quotechar` - CSV quote character, defaults to `b'"'`.
+    /// - `escapechar` - Escape character for quotes, disabled by default.
+    /// - `doublequote` - Treat two consecutive quotes as an escaped quote,
+    ///   defaults to `True`.
+    /// - `quoting` - Enable quoting, defaults to `True`.
+    /// - `comment` - Records starting with this byte are skipped, disabled by
+    ///   default.
+    /// - `trim` - Whitespace trimming, one of `"none"`, `"headers"`,
+    ///   `"fields"` or `"all"`. Defaults to `"none"`.
+    /// - `flexible` - Allow records of unequal lengths, defaults to `False`.
+    /// - `bytes_mode` - Return the fields as `bytes` instead of `str`,
+    ///   without decoding them. Defaults to `False`.
+    /// - `encoding` - Encoding of the CSV, the input is transcoded to UTF-8
+    ///   if set. A UTF-8 or UTF-16 BOM takes precedence over `encoding`, and
+    ///   is stripped.
+    /// - `errors` - How fields that are not valid UTF-8 are handled, either
+    ///   `"strict"` to raise a `rustcsv.error.UTF8Error`, or the name of a
+    ///   Python codec error handler such as `"replace"`, `"ignore"` or
+    ///   `"surrogateescape"`. If `encoding` is set, input that is invalid in
+    ///   the encoding also raises a `rustcsv.error.UTF8Error` if `"strict"`,
+    ///   and is otherwise replaced with U+FFFD. Defaults to `"strict"`.
+    /// - `batch_size` - Yield `list`s of up to `batch_size` records when
+    ///   iterating, instead of one record at a time.
+    /// - `threaded` - Parse the CSV in a background thread, releasing the GIL
+    ///   while waiting for records. Only supported when `path_or_fd` is a
+    ///   path. Defaults to `False`.
+    /// - `dtypes` - `dict` mapping column indices or names to the type to
+    ///   convert the fields of the column to, one of `"str"`, `"int"`,
+    ///   `"float"`, `"bool"` or `"decimal"`, or the corresponding Python type.
+    ///   A `rustcsv.error.ConversionError` is raised if a field can't be
+    ///   converted.
+    /// - `na_values` - Fields equal to any of these strings, e.g. `""` or
+    ///   `"NULL"`, are returned as `None`, also when `dtypes` is set.
+    /// - `quoted_na` - Whether quoted fields may match `na_values`. If
+    ///   `False`, `""` is returned as an empty string while an empty field is
+    ///   `None`, like PostgreSQL's `COPY ... CSV`. Defaults to `True`.
+    /// - `columnar` - Yield batches of records as a `dict` mapping each
+    ///   column name, or index, to a `list` of the column's fields, instead
+    ///   of as `list`s of records. `batch_size` defaults to 65536.
+    /// - `skip_lines` - Number of lines to skip before the header or first
+    ///   record, e.g. a preamble that is not CSV. Lines end at `terminator`
+    ///   outside of quotes, and positions are relative to the first line
+    ///   that is not skipped. Defaults to `0`.
+    /// - `with_position` - Return each record as a `(Position, record)`
+    ///   `tuple`, with the `rustcsv.error.Position` of the record. Not
+    ///   supported together with `columnar`. Defaults to `False`.
+    /// - `threads` - Number of threads to parse the CSV with, in chunks that
+    ///   are returned in order. Only supported when `path_or_fd` is a path.
+    ///   Disabled if `None`.
+    /// - `compression` - Compression of the CSV, either `"gzip"`, `"zstd"`,
+    ///   `"bz2"`, `"xz"`, `"none"` or `"infer"` to detect it from the file
+    ///   extension of a path or the magic number at the start of the CSV.
+    ///   Defaults to `"infer"`.
+    /// - `mmap` - Memory-map the file and parse the mapped bytes, only
+    ///   supported when `path_or_fd` is a path. In `bytes_mode`, fields are
+    ///   returned as `memoryview`s of the mapping, without copying them,
+    ///   except for fields that differ from the bytes in the file, e.g.
+    ///   quoted fields, which are copied. Not supported for compressed
+    ///   files, or together with `threads`, `encoding`, `skip_lines` or
+    ///   `trim` of fields. Defaults to `False`.
+    /// - `dialect` - A `rustcsv.Dialect`, the name of a registered dialect,
+    ///   or a stdlib `csv.Dialect`, whose options are used unless the
+    ///   corresponding argument is set. `quoting` defaults to `False` if its
+    ///   `quote_style` is `"never"`.
     ///
-    /// Note: The `delimiter` and `terminator` [PyBytes] objects must only
-    /// contain a single byte.
+    /// If either `has_headers` or `fieldnames` is set, records are returned
+    /// as `dict`s keyed by the column names.
+    ///
+    /// Note: The `delimiter`, `quotechar`, `escapechar` and `comment`
+    /// [PyBytes] objects must only contain a single byte.
     ///
     ///  [binary file]: https://docs.python.org/3/glossary.html#term-binary-file
     #[new]
@@ -89,34 +629,528 @@ impl CSVReader {
         path_or_fd: &'static PyObjectRef,
         delimiter: Option<&PyBytes>,
         terminator: Option<&PyBytes>,
+        has_headers: Option<bool>,
+        fieldnames: Option<Vec<String>>,
+        quotechar: Option<&PyBytes>,
+        escapechar: Option<&PyBytes>,
+        doublequote: Option<bool>,
+        quoting: Option<bool>,
+        comment: Option<&PyBytes>,
+        trim: Option<String>,
+        flexible: Option<bool>,
+        bytes_mode: Option<bool>,
+        encoding: Option<String>,
+        errors: Option<String>,
+        batch_size: Option<usize>,
+        threaded: Option<bool>,
+        dtypes: Option<&PyObjectRef>,
+        na_values: Option<Vec<String>>,
+        quoted_na: Option<bool>,
+        columnar: Option<bool>,
+        skip_lines: Option<usize>,
+        with_position: Option<bool>,
+        threads: Option<usize>,
+        compression: Option<String>,
+        mmap: Option<bool>,
+        dialect: Option<&PyObjectRef>,
         py: Python,
     ) -> PyResult<()> {
         debug!(
-            "__new__: path_or_fd: {:?}, delimiter: {:?}, terminator: {:?}",
-            path_or_fd, delimiter, terminator
+            "__new__: path_or_fd: {:?}, delimiter: {:?}, terminator: {:?}, \
+             has_headers: {:?}, fieldnames: {:?}, quotechar: {:?}, \
+             escapechar: {:?}, doublequote: {:?}, quoting: {:?}, \
+             comment: {:?}, trim: {:?}, flexible: {:?}, bytes_mode: {:?}, \
+             encoding: {:?}, errors: {:?}, batch_size: {:?}, \
+             threaded: {:?}, dtypes: {:?}, na_values: {:?}, \
+             quoted_na: {:?}, columnar: {:?}, skip_lines: {:?}, \
+             with_position: {:?}, threads: {:?}, compression: {:?}, \
+             mmap: {:?}, dialect: {:?}",
+            path_or_fd,
+            delimiter,
+            terminator,
+            has_headers,
+            fieldnames,
+            quotechar,
+            escapechar,
+            doublequote,
+            quoting,
+            comment,
+            trim,
+            flexible,
+            bytes_mode,
+            encoding,
+            errors,
+            batch_size,
+            threaded,
+            dtypes,
+            na_values,
+            quoted_na,
+            columnar,
+            skip_lines,
+            with_position,
+            threads,
+            compression,
+            mmap,
+            dialect
         );
-        let delimiter_arg = get_optional_single_byte(delimiter, b',')?;
-        let terminator_arg = get_optional_single_byte(terminator, b'\n')?;
+        let has_headers = has_headers.unwrap_or(false);
+        let flexible = flexible.unwrap_or(false);
+        let encoding = match encoding {
+            Some(label) => Some(parse_encoding(py, label.as_str())?),
+            None => None,
+        };
+        let errors = parse_decode_errors(py, errors.unwrap_or("strict".into()).as_str())?;
+        let transcode = encoding.map(|encoding| Transcode {
+            encoding,
+            strict: match errors {
+                record::DecodeErrors::Strict => true,
+                record::DecodeErrors::Handler(_) => false,
+            },
+        });
+        if batch_size == Some(0) {
+            return Err(exc::ValueError::py_err("batch_size must be at least 1"));
+        }
+        let columnar = columnar.unwrap_or(false);
+        let with_position = with_position.unwrap_or(false);
+        if columnar && with_position {
+            return Err(exc::ValueError::py_err(
+                "with_position is not supported together with columnar",
+            ));
+        }
+        let batch_size = match batch_size {
+            None if columnar => Some(DEFAULT_COLUMNAR_BATCH_SIZE),
+            batch_size => batch_size,
+        };
+
+        let mut csv_dialect = Dialect::from_object(py, dialect)?;
+        csv_dialect.update(
+            delimiter,
+            terminator,
+            quotechar,
+            escapechar,
+            doublequote,
+            None,
+            comment,
+        )?;
+        let quoting = quoting.unwrap_or(csv_dialect.quoting());
+        let dialect = ScanDialect {
+            delimiter: csv_dialect.delimiter,
+            terminator: csv_dialect.terminator,
+            quote: csv_dialect.quote,
+            escape: csv_dialect.escape,
+            double_quote: csv_dialect.double_quote,
+            comment: csv_dialect.comment,
+            quoting,
+            has_headers,
+        };
+
+        let trim = parse_trim(trim.unwrap_or("none".into()).as_str())?;
+        let mut builder = csv_dialect.reader_builder(quoting);
+        builder
+            .trim(trim)
+            .flexible(flexible)
+            .has_headers(has_headers);
+
+        let mut source = CSVSource::from_object(py, path_or_fd.to_object(py))?;
+        if mmap.unwrap_or(false) {
+            if threads.is_some() {
+                return Err(exc::ValueError::py_err(
+                    "mmap is not supported together with threads",
+                ));
+            }
+            source = source.into_mapped(py)?;
+        }
+        let mapped = match source {
+            CSVSource::Mapped(ref mapped) => Some(mapped.clone()),
+            _ => None,
+        };
+        let compression =
+            Compression::from_name(compression.as_ref().map_or("infer", |c| c.as_str()))?;
+        let compression = source.detect_compression(compression)?;
+        // The fields of a record are looked up in the mapping by the position
+        // of the record, which must be of the bytes as they are in the file.
+        if mapped.is_some()
+            && (compression != Compression::None
+                || transcode.is_some()
+                || skip_lines.unwrap_or(0) > 0
+                || trim == csv::Trim::Fields
+                || trim == csv::Trim::All)
+        {
+            return Err(exc::ValueError::py_err(
+                "mmap is not supported for compressed files, or together with \
+                 encoding, skip_lines or trim",
+            ));
+        }
 
-        let path_or_fd_obj = path_or_fd.to_object(py);
+        let threaded = threaded.unwrap_or(false);
+        if threaded {
+            if let CSVSource::Readable(_) = source {
+                return Err(exc::ValueError::py_err(
+                    "threaded is only supported when reading from a path",
+                ));
+            }
+        }
 
-        let source = if py.is_instance::<PyString, _>(path_or_fd_obj.as_ref(py))? {
-            // Treat path_or_fd_obj as a path
-            CSVSource::Path(path_or_fd_obj.extract(py)?)
+        // Without quoting, no field is quoted and there's nothing to scan
+        // for.
+        let na_values = na_values.map(|v| NaValues::new(v, quoted_na.unwrap_or(true) || !quoting));
+        let quote_scanner = na_values.as_ref().and_then(|na| na.quote_scanner(dialect));
+
+        // The header record is consumed even if `fieldnames` are given, so
+        // that it's not returned as a data record.
+        let skip_lines = skip_lines.unwrap_or(0);
+        let skip_lines_config = if skip_lines > 0 {
+            Some(SkipLinesConfig {
+                lines: skip_lines,
+                dialect,
+            })
+        } else {
+            None
+        };
+        // Seeking needs the records to be read straight from a seekable
+        // source.
+        let seekable_offset = if transcode.is_none()
+            && quote_scanner.is_none()
+            && skip_lines == 0
+            && !threaded
+            && compression == Compression::None
+        {
+            source.seekable_offset()
         } else {
-            // Treat path_or_fd_obj as a "binary file"
-            CSVSource::Readable(PyFile::from_object(path_or_fd_obj)?)
+            None
+        };
+        let records = if let Some(threads) = threads {
+            if threads == 0 {
+                return Err(exc::ValueError::py_err("threads must be at least 1"));
+            }
+            let path = match source {
+                CSVSource::Path(path) => path,
+                _ => {
+                    return Err(exc::ValueError::py_err(
+                        "threads is only supported when reading from a path",
+                    ))
+                }
+            };
+            if transcode.is_some()
+                || quote_scanner.is_some()
+                || skip_lines > 0
+                || threaded
+                || compression != Compression::None
+            {
+                return Err(exc::ValueError::py_err(
+                    "threads is not supported for compressed files, or together with \
+                     encoding, skip_lines, threaded or quoted_na=False",
+                ));
+            }
+            ParallelRecords::open(path, builder, dialect, flexible, threads)
+                .map(|(header, records)| (header, Records::Iter(Box::new(records))))
+        } else if let Some(start) = seekable_offset {
+            SeekableRecords::new(source, start, &builder, has_headers)
+                .map(|(header, records)| (header, Records::Seekable(records)))
+        } else {
+            make_records_iterator(
+                source,
+                compression,
+                skip_lines_config,
+                &builder,
+                has_headers,
+                transcode,
+                threaded,
+                quote_scanner,
+            )
+            .map(|(header, iter)| (header, Records::Iter(iter)))
+        };
+        let (header, iter) = match records {
+            Ok(res) => res,
+            Err(error) => return Err(csv_error_to_pyerr(error)?),
         };
 
-        match make_records_iterator(source, delimiter_arg, terminator_arg) {
-            Ok(iter) => obj.init(|| CSVReader { iter }),
-            Err(error) => match error.into_kind() {
-                csv::ErrorKind::Io(err) => Err(err.into()),
-                err => Err(exc::IOError::py_err(format!(
-                    "Could not parse CSV: {:?}",
-                    err
-                ))),
-            },
+        // The header read from an empty CSV is an empty record, not a header
+        // without columns.
+        let header = header.filter(|h| !h.is_empty());
+        // Column names are always decoded, even in `bytes_mode`. Invalid
+        // UTF-8 is replaced with U+FFFD unless `errors` is "strict".
+        let header = match (header, &errors) {
+            (Some(h), &record::DecodeErrors::Strict) => {
+                Some(decode_record(h)?.iter().map(String::from).collect())
+            }
+            (Some(h), &record::DecodeErrors::Handler(_)) => Some(
+                h.iter()
+                    .map(|f| String::from_utf8_lossy(f).into_owned())
+                    .collect(),
+            ),
+            (None, _) => None,
+        };
+        let fieldnames = fieldnames.or(header);
+
+        let bytes_mode = bytes_mode.unwrap_or(false);
+        let dtypes = match dtypes {
+            Some(_) if bytes_mode => {
+                return Err(exc::ValueError::py_err(
+                    "dtypes is not supported in bytes_mode",
+                ))
+            }
+            Some(d) => Some(ColumnTypes::from_object(
+                py,
+                d,
+                fieldnames.as_ref().map(|f| f.as_slice()),
+            )?),
+            None => None,
+        };
+        obj.init(|| CSVReader {
+            iter,
+            fieldnames,
+            flexible,
+            bytes_mode,
+            errors,
+            batch_size,
+            dtypes,
+            na_values,
+            columnar,
+            with_position,
+            position: None,
+            mapped,
+        })
+    }
+
+    /// The `rustcsv.error.Position` of the last record read, or `None` if
+    /// no record has been read. Its `line` is `None` if it's unknown, after
+    /// seeking to a position without a line.
+    #[getter]
+    fn position(&self) -> PyResult<Option<PyObject>> {
+        make_optional_error_position(self.position.as_ref())
+    }
+
+    /// The column names, or `None` if records are returned as `tuple`s.
+    #[getter]
+    fn fieldnames(&self) -> PyResult<Option<Vec<String>>> {
+        Ok(self.fieldnames.clone())
+    }
+
+    /// Seeks to `position`, a `rustcsv.error.Position` as returned by
+    /// [CSVReader::position] or `rustcsv.index_position`, so that the next
+    /// record read is the record at `position`. Positions are relative to
+    /// the offset a file object was at when the reader was created.
+    ///
+    /// Seeking is not supported for compressed or non-seekable sources, or
+    /// together with `encoding`, `skip_lines`, `threaded`, `threads` or
+    /// `quoted_na=False`.
+    fn seek(&mut self, position: &PyObjectRef) -> PyResult<()> {
+        let line: Option<u64> = position.getattr("line")?.extract()?;
+        let mut pos = csv::Position::new();
+        pos.set_byte(position.getattr("byte")?.extract()?)
+            .set_line(line.unwrap_or(UNKNOWN_LINE))
+            .set_record(position.getattr("record")?.extract()?);
+        let result = match self.iter {
+            Records::Seekable(ref mut records) => records.seek(pos),
+            Records::Iter(_) => {
+                return Err(exc::ValueError::py_err(
+                    "seek is not supported for compressed or non-seekable sources, or \
+                     together with encoding, skip_lines, threaded, threads or \
+                     quoted_na=False",
+                ))
+            }
+        };
+        if let Err(error) = result {
+            return Err(csv_error_to_pyerr(error)?);
+        }
+        self.position = None;
+        Ok(())
+    }
+
+    /// Reads up to `n` records, returning them as a `list`, or as a `dict`
+    /// of columns if `columnar` is set.
+    ///
+    /// The records are read and converted while holding the GIL once,
+    /// avoiding the per-record overhead of iterating. An empty `list` or
+    /// `dict` is returned once all records have been read.
+    fn read_batch(&mut self, n: usize) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        if self.columnar {
+            Ok(match self.read_columns(py, n)? {
+                Some(columns) => columns,
+                None => PyDict::new(py).to_object(py),
+            })
+        } else {
+            Ok(self.read_rows(py, n)?.to_object(py))
+        }
+    }
+}
+
+impl CSVReader {
+    /// Reads and converts up to `n` records.
+    fn read_rows(&mut self, py: Python, n: usize) -> PyResult<Vec<PyObject>> {
+        let mut rows = Vec::new();
+        while rows.len() < n {
+            match self.iter.next() {
+                Some(Ok(r)) => rows.push(self.next_row(py, r)?),
+                Some(Err(error)) => return Err(csv_error_to_pyerr(error)?),
+                None => break,
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Converts the next record read, updating [CSVReader::position].
+    fn next_row(&mut self, py: Python, r: csv::ByteRecord) -> PyResult<PyObject> {
+        self.position = r.position().cloned();
+        let row = self.record_to_object(py, r)?;
+        if self.with_position {
+            let position = make_optional_error_position(self.position.as_ref())?;
+            Ok((position, row).to_object(py))
+        } else {
+            Ok(row)
+        }
+    }
+
+    /// Reads and converts up to `n` records into a `dict` mapping the
+    /// column names, or the indices of columns without a name, to `list`s of
+    /// fields.
+    ///
+    /// Returns `None` if there are no more records. Fields missing from
+    /// [CSVReader::flexible] records are `None`.
+    fn read_columns(&mut self, py: Python, n: usize) -> PyResult<Option<PyObject>> {
+        let names = self.fieldnames.as_ref().map_or(&[][..], |f| f.as_slice());
+        let mut columns: Vec<Vec<PyObject>> = names.iter().map(|_| Vec::new()).collect();
+        let mut rows = 0;
+        while rows < n {
+            let r = match self.iter.next() {
+                Some(Ok(r)) => r,
+                Some(Err(error)) => return Err(csv_error_to_pyerr(error)?),
+                None => break,
+            };
+            self.position = r.position().cloned();
+            let missing = self.check_record(&r)?;
+            let fields = match self.mapped {
+                Some(ref mapped) if self.bytes_mode => {
+                    record::MappedRecord::new(r, mapped, missing).into_items(py)?
+                }
+                _ => record::TypedRecord::new(
+                    r,
+                    self.dtypes.as_ref(),
+                    &self.errors,
+                    self.bytes_mode,
+                    missing,
+                )
+                .into_items(py, self.fieldnames.as_ref().map(|f| f.as_slice()))?,
+            };
+
+            while columns.len() < fields.len() {
+                columns.push((0..rows).map(|_| py.None()).collect());
+            }
+            let len = fields.len();
+            for (column, field) in columns.iter_mut().zip(fields.into_iter()) {
+                column.push(field);
+            }
+            for column in columns[len..].iter_mut() {
+                column.push(py.None());
+            }
+            rows += 1;
+        }
+        if rows == 0 {
+            return Ok(None);
+        }
+
+        let dict = PyDict::new(py);
+        for (index, column) in columns.into_iter().enumerate() {
+            let column = PyList::new(py, &column);
+            match names.get(index) {
+                Some(name) => dict.set_item(name, column)?,
+                None => dict.set_item(index, column)?,
+            }
+        }
+        Ok(Some(dict.to_object(py)))
+    }
+
+    /// Checks the length of a record against [CSVReader::fieldnames], and
+    /// finds its missing fields.
+    fn check_record(&self, r: &csv::ByteRecord) -> PyResult<Option<Vec<bool>>> {
+        let missing = match self.na_values {
+            Some(ref na_values) => na_values.find_missing(r)?,
+            None => None,
+        };
+
+        if let Some(ref fieldnames) = self.fieldnames {
+            if !self.flexible && r.len() != fieldnames.len() {
+                let position = make_optional_error_position(r.position())?;
+                return Err(UnequalLengthsError::py_err((
+                    format!(
+                        "Unequal lengths: Expected length {:?} got length {:?}",
+                        fieldnames.len(),
+                        r.len(),
+                    ),
+                    position,
+                )));
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Converts a record into a Python `tuple` or `dict` of `str`, `bytes`,
+    /// `memoryview` or the types in [CSVReader::dtypes], and `None` for
+    /// fields matching [CSVReader::na_values].
+    fn record_to_object(&self, py: Python, r: csv::ByteRecord) -> PyResult<PyObject> {
+        let missing = self.check_record(&r)?;
+        if let Some(ref mapped) = self.mapped {
+            if self.bytes_mode {
+                let rec = record::MappedRecord::new(r, mapped, missing);
+                return match self.fieldnames {
+                    Some(ref fieldnames) => rec.into_dict(py, fieldnames),
+                    None => rec.into_object(py),
+                };
+            }
+        }
+        if self.dtypes.is_some() || missing.is_some() {
+            let rec = record::TypedRecord::new(
+                r,
+                self.dtypes.as_ref(),
+                &self.errors,
+                self.bytes_mode,
+                missing,
+            );
+            return match self.fieldnames {
+                Some(ref fieldnames) => rec.into_dict(py, fieldnames),
+                None => rec.into_object(py),
+            };
+        }
+
+        if self.bytes_mode {
+            let rec: record::BytesRecord = r.into();
+            match self.fieldnames {
+                Some(ref fieldnames) => rec.into_dict(py, fieldnames),
+                None => Ok(rec.into_object(py)),
+            }
+        } else {
+            match self.errors {
+                record::DecodeErrors::Strict => {
+                    let rec: record::Record = decode_record(r)?.into();
+                    match self.fieldnames {
+                        Some(ref fieldnames) => rec.into_dict(py, fieldnames),
+                        None => Ok(rec.into_object(py)),
+                    }
+                }
+                record::DecodeErrors::Handler(ref errors) => {
+                    let rec = record::LossyRecord::new(r, errors);
+                    match self.fieldnames {
+                        Some(ref fieldnames) => rec.into_dict(py, fieldnames),
+                        None => rec.into_object(py),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Decodes a [csv::ByteRecord] as UTF-8, raising a
+/// `rustcsv.error.UTF8Error` if it's not valid UTF-8.
+fn decode_record(r: csv::ByteRecord) -> PyResult<csv::StringRecord> {
+    match csv::StringRecord::from_byte_record(r) {
+        Ok(r) => Ok(r),
+        Err(error) => {
+            let message = format!("{:?}", error.utf8_error());
+            let position = make_optional_error_position(error.into_byte_record().position())?;
+            Err(UTF8Error::py_err((message, position)))
         }
     }
 }
@@ -130,9 +1164,57 @@ pub fn make_error_position(pos: csv::Position) -> PyResult<PyObject> {
     let py = gil.python();
     let errors_mod = py.import("rustcsv.error")?;
     let position_type = errors_mod.get("Position")?;
+    let line = if pos.line() < UNKNOWN_LINE {
+        Some(pos.line())
+    } else {
+        None
+    };
     Ok(position_type
         .to_object(py)
-        .call1(py, (pos.byte(), pos.line(), pos.record()))?)
+        .call1(py, (pos.byte(), line, pos.record()))?)
+}
+
+/// Create a Python rustcsv.error.Position object from an optional csv::Position
+pub fn make_optional_error_position(pos: Option<&csv::Position>) -> PyResult<Option<PyObject>> {
+    match pos {
+        Some(p) => Ok(Some(make_error_position(p.clone())?)),
+        None => Ok(None),
+    }
+}
+
+/// Converts a [csv::Error] raised while reading records into a [PyErr].
+///
+/// An `Err` is returned if the error position could not be created.
+pub fn csv_error_to_pyerr(error: csv::Error) -> PyResult<PyErr> {
+    Ok(match error.into_kind() {
+        csv::ErrorKind::Io(err) => {
+            if err.get_ref().map_or(false, |e| e.is::<DecodeError>()) {
+                UTF8Error::py_err((err.to_string(), None::<PyObject>))
+            } else {
+                error!("IO error: {:?}", err);
+                PyErr::from(err)
+            }
+        }
+        csv::ErrorKind::Utf8 { pos, err } => {
+            let position = make_optional_error_position(pos.as_ref())?;
+            UTF8Error::py_err((format!("{:?}", err), position))
+        }
+        csv::ErrorKind::UnequalLengths {
+            pos,
+            expected_len,
+            len,
+        } => {
+            let position = make_optional_error_position(pos.as_ref())?;
+            UnequalLengthsError::py_err((
+                format!(
+                    "Unequal lengths: Expected length {:?} got length {:?}",
+                    expected_len, len,
+                ),
+                position,
+            ))
+        }
+        not_io_error => exc::ValueError::py_err(format!("CSV parsing error: {:?}", not_io_error)),
+    })
 }
 
 #[pyproto]
@@ -142,51 +1224,30 @@ impl PyIterProtocol for CSVReader {
         Ok(self.into())
     }
 
-    /// Read the next record from [CSVReader::iter]
+    /// Read the next record, or batch of records, from [CSVReader::iter]
     fn __next__(&mut self) -> PyResult<Option<PyObject>> {
         debug!("__next__");
+        if let Some(batch_size) = self.batch_size {
+            let gil = Python::acquire_gil();
+            let py = gil.python();
+            if self.columnar {
+                return self.read_columns(py, batch_size);
+            }
+            let rows = self.read_rows(py, batch_size)?;
+            if rows.is_empty() {
+                debug!("Reached end");
+                return Ok(None);
+            }
+            return Ok(Some(rows.to_object(py)));
+        }
         match self.iter.next() {
             Some(res) => match res {
                 Ok(r) => {
                     let gil = Python::acquire_gil();
                     let py = gil.python();
-                    let rec: record::Record = r.into();
-                    Ok(Some(rec.into_object(py)))
+                    Ok(Some(self.next_row(py, r)?))
                 }
-                Err(error) => match error.into_kind() {
-                    csv::ErrorKind::Io(err) => {
-                        error!("IO error: {:?}", err);
-                        Err(PyErr::from(err))
-                    }
-                    csv::ErrorKind::Utf8 { pos, err } => {
-                        let position = match pos {
-                            Some(p) => Some(make_error_position(p.clone())?),
-                            None => None,
-                        };
-                        Err(UTF8Error::py_err((format!("{:?}", err), position)))
-                    }
-                    csv::ErrorKind::UnequalLengths {
-                        pos,
-                        expected_len,
-                        len,
-                    } => {
-                        let position = match pos {
-                            Some(p) => Some(make_error_position(p.clone())?),
-                            None => None,
-                        };
-                        Err(UnequalLengthsError::py_err((
-                            format!(
-                                "Unequal lengths: Expected length {:?} got length {:?}",
-                                expected_len, len,
-                            ),
-                            position,
-                        )))
-                    }
-                    not_io_error => Err(exc::ValueError::py_err(format!(
-                        "CSV parsing error: {:?}",
-                        not_io_error
-                    ))),
-                },
+                Err(error) => Err(csv_error_to_pyerr(error)?),
             },
             None => {
                 debug!("Reached end");
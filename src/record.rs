@@ -1,13 +1,21 @@
 extern crate csv;
 extern crate pyo3;
 
+use convert::{conversion_error, ColumnTypes};
+use mmap::MappedFile;
+use pyo3::types::PyBytes;
+use pyo3::types::PyDict;
 use pyo3::types::PyTuple;
 use pyo3::IntoPyObject;
 use pyo3::IntoPyTuple;
 use pyo3::Py;
 use pyo3::PyObject;
+use pyo3::PyResult;
 use pyo3::Python;
+use pyo3::ToPyObject;
+use reader::{make_optional_error_position, UTF8Error};
 use std::convert;
+use std::str;
 
 pub struct Record {
     r: csv::StringRecord,
@@ -19,6 +27,17 @@ impl convert::From<csv::StringRecord> for Record {
     }
 }
 
+impl Record {
+    /// Converts the record into a Python `dict`, keyed by `fieldnames`.
+    ///
+    /// Like `csv.DictReader`, fields missing from the record are set to
+    /// `None` and any extra fields are put in a `list` under the `None` key.
+    pub fn into_dict(self, py: Python, fieldnames: &[String]) -> PyResult<PyObject> {
+        let items: Vec<&str> = self.r.iter().collect();
+        items_into_dict(py, fieldnames, items)
+    }
+}
+
 impl IntoPyObject for Record {
     fn into_object(self, py: Python) -> PyObject {
         self.into_tuple(py).into()
@@ -31,3 +50,304 @@ impl IntoPyTuple for Record {
         PyTuple::new(py, items)
     }
 }
+
+/// Like [Record], but the fields are converted to Python `bytes` instead of
+/// `str`.
+pub struct BytesRecord {
+    r: csv::ByteRecord,
+}
+
+impl convert::From<csv::ByteRecord> for BytesRecord {
+    fn from(record: csv::ByteRecord) -> Self {
+        BytesRecord { r: record }
+    }
+}
+
+impl BytesRecord {
+    /// Converts the record into a Python `dict`, keyed by `fieldnames`.
+    ///
+    /// See [Record::into_dict].
+    pub fn into_dict(self, py: Python, fieldnames: &[String]) -> PyResult<PyObject> {
+        let items: Vec<Py<PyBytes>> = self.r.iter().map(|f| PyBytes::new(py, f)).collect();
+        items_into_dict(py, fieldnames, items)
+    }
+}
+
+impl IntoPyObject for BytesRecord {
+    fn into_object(self, py: Python) -> PyObject {
+        self.into_tuple(py).into()
+    }
+}
+
+impl IntoPyTuple for BytesRecord {
+    fn into_tuple(self, py: Python) -> Py<PyTuple> {
+        let items: Vec<Py<PyBytes>> = self.r.iter().map(|f| PyBytes::new(py, f)).collect();
+        PyTuple::new(py, items)
+    }
+}
+
+/// Like [BytesRecord], but the fields are `memoryview`s of a [MappedFile],
+/// and missing fields are `None`.
+///
+/// Fields are found in the mapping from the position of the record, a field
+/// that differs from the mapped bytes, e.g. because it's quoted, is copied
+/// and so are the fields after it.
+pub struct MappedRecord<'m> {
+    r: csv::ByteRecord,
+    mapped: &'m MappedFile,
+    /// Which fields are missing, see [TypedRecord].
+    missing: Option<Vec<bool>>,
+}
+
+impl<'m> MappedRecord<'m> {
+    pub fn new(
+        record: csv::ByteRecord,
+        mapped: &'m MappedFile,
+        missing: Option<Vec<bool>>,
+    ) -> Self {
+        MappedRecord {
+            r: record,
+            mapped,
+            missing,
+        }
+    }
+
+    /// Converts the fields into `memoryview`s, or `None` if missing.
+    pub fn into_items(self, py: Python) -> PyResult<Vec<PyObject>> {
+        let mut offset = self.r.position().map(|pos| pos.byte() as usize);
+        let mut items = Vec::with_capacity(self.r.len());
+        for (column, field) in self.r.iter().enumerate() {
+            // Once a field is not found, the offsets of the rest are unknown.
+            offset = offset.filter(|&start| self.mapped.contains(start, field));
+            if self
+                .missing
+                .as_ref()
+                .map_or(false, |m| m.get(column) == Some(&true))
+            {
+                items.push(py.None());
+            } else {
+                items.push(match offset {
+                    Some(start) => self.mapped.view(py, start, field.len())?,
+                    None => self.mapped.copy(py, field)?,
+                });
+            }
+            offset = offset.map(|start| start + field.len() + 1);
+        }
+        Ok(items)
+    }
+
+    /// Converts the record into a Python `tuple`.
+    pub fn into_object(self, py: Python) -> PyResult<PyObject> {
+        let items = self.into_items(py)?;
+        Ok(PyTuple::new(py, items).into())
+    }
+
+    /// Converts the record into a Python `dict`, keyed by `fieldnames`.
+    ///
+    /// See [Record::into_dict].
+    pub fn into_dict(self, py: Python, fieldnames: &[String]) -> PyResult<PyObject> {
+        let items = self.into_items(py)?;
+        items_into_dict(py, fieldnames, items)
+    }
+}
+
+/// How invalid UTF-8 in a field is handled.
+pub enum DecodeErrors {
+    /// Raise a `rustcsv.error.UTF8Error`.
+    Strict,
+    /// Decode the field using a Python [codec error handler], e.g.
+    /// `"replace"` or `"surrogateescape"`.
+    ///
+    ///  [codec error handler]: https://docs.python.org/3/library/codecs.html#error-handlers
+    Handler(String),
+}
+
+/// Like [Record], but fields that are not valid UTF-8 are decoded using a
+/// [DecodeErrors::Handler] instead of raising an error.
+pub struct LossyRecord<'e> {
+    r: csv::ByteRecord,
+    /// Name of the Python codec error handler.
+    errors: &'e str,
+}
+
+impl<'e> LossyRecord<'e> {
+    pub fn new(record: csv::ByteRecord, errors: &'e str) -> Self {
+        LossyRecord { r: record, errors }
+    }
+
+    fn decode_fields(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        self.r
+            .iter()
+            .map(|field| decode_field(py, field, self.errors))
+            .collect()
+    }
+
+    /// Converts the record into a Python `tuple`.
+    pub fn into_object(self, py: Python) -> PyResult<PyObject> {
+        let items = self.decode_fields(py)?;
+        Ok(PyTuple::new(py, items).into())
+    }
+
+    /// Converts the record into a Python `dict`, keyed by `fieldnames`.
+    ///
+    /// See [Record::into_dict].
+    pub fn into_dict(self, py: Python, fieldnames: &[String]) -> PyResult<PyObject> {
+        let items = self.decode_fields(py)?;
+        items_into_dict(py, fieldnames, items)
+    }
+}
+
+/// Like [Record], but the fields are converted according to [ColumnTypes]
+/// and missing fields are `None`.
+///
+/// Fields of columns without a dtype are decoded like [Record] or
+/// [LossyRecord], depending on `errors`, or are `bytes` in `bytes_mode`.
+pub struct TypedRecord<'a> {
+    r: csv::ByteRecord,
+    dtypes: Option<&'a ColumnTypes>,
+    errors: &'a DecodeErrors,
+    bytes_mode: bool,
+    /// Which fields are missing values, see [missing::NaValues].
+    missing: Option<Vec<bool>>,
+}
+
+impl<'a> TypedRecord<'a> {
+    pub fn new(
+        record: csv::ByteRecord,
+        dtypes: Option<&'a ColumnTypes>,
+        errors: &'a DecodeErrors,
+        bytes_mode: bool,
+        missing: Option<Vec<bool>>,
+    ) -> Self {
+        TypedRecord {
+            r: record,
+            dtypes,
+            errors,
+            bytes_mode,
+            missing,
+        }
+    }
+
+    fn is_missing(&self, column: usize) -> bool {
+        match self.missing {
+            Some(ref missing) => missing.get(column) == Some(&true),
+            None => false,
+        }
+    }
+
+    /// Converts the fields, raising a `rustcsv.error.ConversionError` naming
+    /// the column by its name in `fieldnames`, or by its index.
+    fn convert_fields(&self, py: Python, fieldnames: Option<&[String]>) -> PyResult<Vec<PyObject>> {
+        let mut items = Vec::with_capacity(self.r.len());
+        for (column, field) in self.r.iter().enumerate() {
+            if self.is_missing(column) {
+                items.push(py.None());
+                continue;
+            }
+            let (dtypes, dtype) = match self.dtypes.and_then(|d| d.get(column).map(|t| (d, t))) {
+                Some(column_type) => column_type,
+                None => {
+                    items.push(self.decode(py, column, field)?);
+                    continue;
+                }
+            };
+            let converted = str::from_utf8(field)
+                .ok()
+                .and_then(|text| dtypes.convert(py, dtype, text));
+            match converted {
+                Some(value) => items.push(value),
+                None => {
+                    let value = String::from_utf8_lossy(field);
+                    return Err(match fieldnames.and_then(|f| f.get(column)) {
+                        Some(name) => conversion_error(dtype, self.r.position(), name, &value)?,
+                        None => conversion_error(dtype, self.r.position(), column, &value)?,
+                    });
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    /// Decodes a field of a column without a dtype.
+    fn decode(&self, py: Python, column: usize, field: &[u8]) -> PyResult<PyObject> {
+        if self.bytes_mode {
+            return Ok(PyBytes::new(py, field).into());
+        }
+        match *self.errors {
+            DecodeErrors::Handler(ref errors) => decode_field(py, field, errors),
+            DecodeErrors::Strict => match str::from_utf8(field) {
+                Ok(s) => Ok(s.to_object(py)),
+                Err(error) => Err(utf8_error(
+                    format!("invalid utf-8 in field {}: {}", column, error),
+                    self.r.position(),
+                )?),
+            },
+        }
+    }
+
+    /// Converts the fields, without collecting them into a `tuple` or
+    /// `dict`.
+    ///
+    /// `fieldnames` are only used to name the column in errors.
+    pub fn into_items(self, py: Python, fieldnames: Option<&[String]>) -> PyResult<Vec<PyObject>> {
+        self.convert_fields(py, fieldnames)
+    }
+
+    /// Converts the record into a Python `tuple`.
+    pub fn into_object(self, py: Python) -> PyResult<PyObject> {
+        let items = self.convert_fields(py, None)?;
+        Ok(PyTuple::new(py, items).into())
+    }
+
+    /// Converts the record into a Python `dict`, keyed by `fieldnames`.
+    ///
+    /// See [Record::into_dict].
+    pub fn into_dict(self, py: Python, fieldnames: &[String]) -> PyResult<PyObject> {
+        let items = self.convert_fields(py, Some(fieldnames))?;
+        items_into_dict(py, fieldnames, items)
+    }
+}
+
+/// Decodes a field as UTF-8, only calling into Python to apply the `errors`
+/// handler if the field is not valid UTF-8.
+fn decode_field(py: Python, field: &[u8], errors: &str) -> PyResult<PyObject> {
+    match str::from_utf8(field) {
+        Ok(s) => Ok(s.to_object(py)),
+        Err(_) => {
+            let bytes: PyObject = PyBytes::new(py, field).into();
+            bytes.call_method1(py, "decode", ("utf-8", errors))
+        }
+    }
+}
+
+/// Creates a `rustcsv.error.UTF8Error`.
+fn utf8_error(message: String, position: Option<&csv::Position>) -> PyResult<PyErr> {
+    Ok(UTF8Error::py_err((
+        message,
+        make_optional_error_position(position)?,
+    )))
+}
+
+fn items_into_dict<T: ToPyObject>(
+    py: Python,
+    fieldnames: &[String],
+    mut items: Vec<T>,
+) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    let extras = if items.len() > fieldnames.len() {
+        items.split_off(fieldnames.len())
+    } else {
+        Vec::new()
+    };
+    let mut items = items.into_iter();
+    for key in fieldnames.iter() {
+        match items.next() {
+            Some(value) => dict.set_item(key, value)?,
+            None => dict.set_item(key, py.None())?,
+        }
+    }
+    if !extras.is_empty() {
+        dict.set_item(py.None(), extras)?;
+    }
+    Ok(dict.to_object(py))
+}
@@ -0,0 +1,368 @@
+//! Detecting the dialect of a CSV from a sample of it.
+//!
+//! Every combination of candidate delimiter and quote character is used to
+//! parse the sample, and the one whose records most consistently have the
+//! same number of fields, more than one, is picked. Earlier candidates win
+//! ties, e.g. `,` over `:` in records that all contain a time.
+//!
+//! Whether the first record is a header is decided like Python's
+//! [csv.Sniffer.has_header], by comparing its fields to the fields of the
+//! same column in the other records.
+//!
+//!  [csv.Sniffer.has_header]: https://docs.python.org/3/library/csv.html#csv.Sniffer.has_header
+extern crate csv;
+extern crate pyo3;
+
+use compression::Compression;
+use py_file::PyFile;
+use pyo3::exceptions as exc;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyObjectRef};
+use pyo3::PyObject;
+use pyo3::PyResult;
+use pyo3::Python;
+use reader::{csv_error_to_pyerr, CSVSource};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::str;
+use util::terminator_bytes;
+
+/// Default number of bytes sniffed.
+const DEFAULT_SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Delimiters tried if none are given, in order of preference.
+const DEFAULT_DELIMITERS: &[u8] = b",\t;|:";
+
+/// Quote characters tried, in order of preference.
+const QUOTES: &[u8] = b"\"'";
+
+/// The dialect of a sample, as detected by [sniff_sample].
+#[derive(Debug)]
+struct Sniffed {
+    delimiter: u8,
+    quote: u8,
+    escape: Option<u8>,
+    double_quote: bool,
+    terminator: csv::Terminator,
+    has_headers: bool,
+    columns: usize,
+}
+
+/// How well a dialect fits a sample.
+struct Candidate {
+    delimiter: u8,
+    quote: u8,
+    escape: Option<u8>,
+    double_quote: bool,
+    /// The most common number of fields.
+    columns: usize,
+    /// Fraction of the records with `columns` fields.
+    consistency: f64,
+    records: Vec<csv::ByteRecord>,
+}
+
+impl Candidate {
+    /// Whether this candidate fits the sample better than `other`.
+    fn is_better_than(&self, other: &Candidate) -> bool {
+        (self.columns > 1, self.consistency) > (other.columns > 1, other.consistency)
+    }
+}
+
+/// Detects the terminator of the records in `sample`, `\r\n` if it's at
+/// least as common as a lone `\n`.
+fn sniff_terminator(sample: &[u8]) -> csv::Terminator {
+    let (mut crlf, mut lf, mut cr) = (0, 0, 0);
+    for (i, &b) in sample.iter().enumerate() {
+        match b {
+            b'\n' if i > 0 && sample[i - 1] == b'\r' => crlf += 1,
+            b'\n' => lf += 1,
+            b'\r' if sample.get(i + 1) != Some(&b'\n') => cr += 1,
+            _ => (),
+        }
+    }
+    if crlf > 0 && crlf >= lf {
+        csv::Terminator::CRLF
+    } else if lf == 0 && cr > 0 {
+        csv::Terminator::Any(b'\r')
+    } else {
+        csv::Terminator::Any(b'\n')
+    }
+}
+
+/// Detects how `quote` is escaped in quoted fields, returning the escape
+/// character and whether quotes are doubled.
+///
+/// A backslash is the escape character if `\"` is more common than `""`.
+fn sniff_escape(sample: &[u8], quote: u8) -> (Option<u8>, bool) {
+    let (mut escaped, mut doubled) = (0, 0);
+    let mut in_quotes = false;
+    let mut i = 0;
+    while i < sample.len() {
+        let b = sample[i];
+        let next = sample.get(i + 1);
+        if in_quotes && b == b'\\' && next == Some(&quote) {
+            escaped += 1;
+            i += 1;
+        } else if in_quotes && b == quote && next == Some(&quote) {
+            doubled += 1;
+            i += 1;
+        } else if b == quote {
+            in_quotes = !in_quotes;
+        }
+        i += 1;
+    }
+    if escaped > doubled {
+        (Some(b'\\'), false)
+    } else {
+        (None, true)
+    }
+}
+
+/// Parses `sample` with `delimiter` and `quote`, returning `None` if it
+/// fails to parse.
+///
+/// If `truncated` is set, the last record is ignored as it may be cut off.
+fn try_candidate(
+    sample: &[u8],
+    truncated: bool,
+    terminator: csv::Terminator,
+    delimiter: u8,
+    quote: u8,
+) -> Option<Candidate> {
+    let (escape, double_quote) = sniff_escape(sample, quote);
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quote(quote)
+        .escape(escape)
+        .double_quote(double_quote)
+        .terminator(terminator)
+        .flexible(true)
+        .has_headers(false)
+        .from_reader(sample);
+    let mut records = rdr
+        .byte_records()
+        .collect::<csv::Result<Vec<csv::ByteRecord>>>()
+        .ok()?;
+    if truncated && records.len() > 1 {
+        records.pop();
+    }
+    let mut counts = HashMap::new();
+    for record in records.iter() {
+        *counts.entry(record.len()).or_insert(0) += 1;
+    }
+    let (columns, count) = counts
+        .into_iter()
+        .max_by_key(|&(columns, count)| (count, columns))?;
+    Some(Candidate {
+        delimiter,
+        quote,
+        escape,
+        double_quote,
+        columns,
+        consistency: count as f64 / records.len() as f64,
+        records,
+    })
+}
+
+fn is_number(field: &[u8]) -> bool {
+    str::from_utf8(field)
+        .ok()
+        .and_then(|s| s.trim().parse::<f64>().ok())
+        .is_some()
+}
+
+/// Guesses whether the first of `records` is a header.
+///
+/// Each column votes for a header if the other records' fields are all
+/// numbers while the first isn't, or all have the same length, which
+/// differs from the length of the first. It votes against a header
+/// otherwise, unless the other fields have neither in common.
+fn sniff_header(records: &[csv::ByteRecord], columns: usize) -> bool {
+    let (first, rest) = match records.split_first() {
+        Some((first, rest)) if first.len() == columns && !rest.is_empty() => (first, rest),
+        _ => return false,
+    };
+    let rest: Vec<&csv::ByteRecord> = rest.iter().filter(|r| r.len() == columns).collect();
+    let mut votes = 0;
+    for (column, header) in first.iter().enumerate() {
+        let fields: Vec<&[u8]> = rest.iter().map(|r| &r[column]).collect();
+        if fields.is_empty() {
+            continue;
+        }
+        if fields.iter().all(|f| is_number(f)) {
+            votes += if is_number(header) { -1 } else { 1 };
+        } else if fields.iter().all(|f| f.len() == fields[0].len()) {
+            votes += if header.len() == fields[0].len() {
+                -1
+            } else {
+                1
+            };
+        }
+    }
+    votes > 0
+}
+
+/// Detects the dialect of `sample`, trying each of `delimiters`.
+fn sniff_sample(sample: &[u8], truncated: bool, delimiters: &[u8]) -> Sniffed {
+    let terminator = sniff_terminator(sample);
+    let mut best: Option<Candidate> = None;
+    for &quote in QUOTES {
+        for &delimiter in delimiters {
+            let candidate = match try_candidate(sample, truncated, terminator, delimiter, quote) {
+                Some(candidate) => candidate,
+                None => continue,
+            };
+            if best
+                .as_ref()
+                .map_or(true, |best| candidate.is_better_than(best))
+            {
+                best = Some(candidate);
+            }
+        }
+    }
+    match best {
+        Some(best) => Sniffed {
+            delimiter: best.delimiter,
+            quote: best.quote,
+            escape: best.escape,
+            double_quote: best.double_quote,
+            terminator,
+            has_headers: sniff_header(&best.records, best.columns),
+            columns: best.columns,
+        },
+        None => Sniffed {
+            delimiter: delimiters.first().cloned().unwrap_or(b','),
+            quote: b'"',
+            escape: None,
+            double_quote: true,
+            terminator,
+            has_headers: false,
+            columns: 0,
+        },
+    }
+}
+
+/// Reads up to `sample_size` bytes from the start of `source`, returning
+/// them and whether there's more.
+fn read_sample(mut source: CSVSource, sample_size: usize) -> csv::Result<(Vec<u8>, bool)> {
+    let compression = source.detect_compression(Compression::Infer)?;
+    let mut sample = Vec::new();
+    source
+        .into_reader(compression)?
+        .take(sample_size as u64 + 1)
+        .read_to_end(&mut sample)?;
+    let truncated = sample.len() > sample_size;
+    sample.truncate(sample_size);
+    Ok((sample, truncated))
+}
+
+/// sniff(path_or_fd, sample_size=None, delimiters=None)
+/// --
+/// Detects the dialect of a CSV from up to `sample_size` bytes at its start,
+/// returning a `rustcsv.sniffer.SniffedDialect`.
+///
+/// A [binary file] is read from its current position, and seeked back to it
+/// if it's seekable. Compressed CSVs are decompressed, like by `CSVReader`.
+///
+/// - `path_or_fd` - Either a string path to a file or a [binary file].
+/// - `sample_size` - Number of bytes to read, defaults to 65536.
+/// - `delimiters` - The candidate delimiters, defaults to `b",\t;|:"`.
+///
+///  [binary file]: https://docs.python.org/3/glossary.html#term-binary-file
+#[pyfunction]
+pub fn sniff(
+    path_or_fd: &PyObjectRef,
+    sample_size: Option<usize>,
+    delimiters: Option<&PyBytes>,
+    py: Python,
+) -> PyResult<PyObject> {
+    let sample_size = sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE);
+    if sample_size == 0 {
+        return Err(exc::ValueError::py_err("sample_size must be at least 1"));
+    }
+    let delimiters = delimiters.map_or(DEFAULT_DELIMITERS, |d| d.as_bytes());
+    if delimiters.is_empty() {
+        return Err(exc::ValueError::py_err("delimiters must not be empty"));
+    }
+
+    let mut source = match CSVSource::from_object(py, path_or_fd.to_object(py))? {
+        // Reading through the methods of the file object, rather than its
+        // file descriptor, keeps its buffer consistent with seeking back.
+        CSVSource::Readable(readable) => CSVSource::Readable(readable.without_fd()),
+        source => source,
+    };
+    let start = match source {
+        CSVSource::Readable(ref mut readable) => readable.seekable_offset(),
+        _ => None,
+    };
+    let (sample, truncated) = match read_sample(source, sample_size) {
+        Ok(res) => res,
+        Err(error) => return Err(csv_error_to_pyerr(error)?),
+    };
+    if let Some(start) = start {
+        PyFile::from_object(path_or_fd.to_object(py))?
+            .without_fd()
+            .seek(SeekFrom::Start(start))?;
+    }
+    if sample.is_empty() {
+        return Err(exc::ValueError::py_err(
+            "Could not sniff the dialect of an empty CSV",
+        ));
+    }
+
+    let sniffed = sniff_sample(&sample, truncated, delimiters);
+    debug!("Sniffed {:?}", sniffed);
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("delimiter", PyBytes::new(py, &[sniffed.delimiter]))?;
+    kwargs.set_item("quotechar", PyBytes::new(py, &[sniffed.quote]))?;
+    kwargs.set_item(
+        "escapechar",
+        sniffed.escape.map(|escape| PyBytes::new(py, &[escape])),
+    )?;
+    kwargs.set_item("doublequote", sniffed.double_quote)?;
+    kwargs.set_item(
+        "terminator",
+        PyBytes::new(py, &terminator_bytes(sniffed.terminator)),
+    )?;
+    kwargs.set_item("has_headers", sniffed.has_headers)?;
+    kwargs.set_item("columns", sniffed.columns)?;
+    py.import("rustcsv.sniffer")?
+        .get("SniffedDialect")?
+        .call((), Some(kwargs))
+        .map(|dialect| dialect.to_object(py))
+}
+
+/// Adds the functions of this module to the `_rustcsv` module.
+pub fn register(m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_function!(sniff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_sample() {
+        let sniffed = sniff_sample(
+            b"name;price\r\nfoo;1,5\r\nbar;2,5\r\n",
+            false,
+            DEFAULT_DELIMITERS,
+        );
+        assert_eq!(sniffed.delimiter, b';');
+        match sniffed.terminator {
+            csv::Terminator::CRLF => (),
+            terminator => panic!("Unexpected terminator {:?}", terminator),
+        }
+        assert!(sniffed.has_headers);
+        assert_eq!(sniffed.columns, 2);
+    }
+
+    #[test]
+    fn test_sniff_escape() {
+        assert_eq!(sniff_escape(br#"a,"b ""c"" d""#, b'"'), (None, true));
+        assert_eq!(
+            sniff_escape(br#"a,"b \"c\" d""#, b'"'),
+            (Some(b'\\'), false)
+        );
+    }
+}
@@ -0,0 +1,82 @@
+extern crate pyo3;
+
+use pyo3::Python;
+use std::sync::mpsc;
+use std::thread;
+use std::vec;
+
+/// Number of items sent over the channel at a time.
+const CHUNK_SIZE: usize = 1024;
+
+/// Number of chunks the worker thread may read ahead.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// An [Iterator] over the items of another iterator, which is advanced in a
+/// background thread.
+///
+/// The items are sent from the worker thread in chunks over a bounded
+/// channel, the GIL is released while waiting for the next chunk. Dropping the
+/// [ThreadedIter] stops the worker thread once it tries to send its next
+/// chunk.
+pub struct ThreadedIter<T: Send + 'static> {
+    /// Always `Some`, except while waiting for the next chunk.
+    receiver: Option<mpsc::Receiver<Vec<T>>>,
+    /// The current chunk.
+    chunk: vec::IntoIter<T>,
+}
+
+impl<T: Send + 'static> ThreadedIter<T> {
+    /// Spawns a thread that iterates over `iter`.
+    pub fn spawn<I>(iter: I) -> ThreadedIter<T>
+    where
+        I: Iterator<Item = T> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        thread::spawn(move || {
+            let mut iter = iter.fuse();
+            loop {
+                let chunk: Vec<T> = iter.by_ref().take(CHUNK_SIZE).collect();
+                if chunk.is_empty() {
+                    debug!("Worker thread reached end");
+                    return;
+                }
+                if sender.send(chunk).is_err() {
+                    debug!("Receiver dropped, stopping worker thread");
+                    return;
+                }
+            }
+        });
+        ThreadedIter {
+            receiver: Some(receiver),
+            chunk: Vec::new().into_iter(),
+        }
+    }
+
+    /// Waits for the next chunk from the worker thread, releasing the GIL.
+    fn next_chunk(&mut self) -> Option<Vec<T>> {
+        let receiver = self.receiver.take()?;
+        let gil = Python::acquire_gil();
+        let (receiver, chunk) = gil.python().allow_threads(move || {
+            let chunk = receiver.recv().ok();
+            (receiver, chunk)
+        });
+        self.receiver = Some(receiver);
+        chunk
+    }
+}
+
+impl<T: Send + 'static> Iterator for ThreadedIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            if let Some(item) = self.chunk.next() {
+                return Some(item);
+            }
+            match self.next_chunk() {
+                Some(chunk) => self.chunk = chunk.into_iter(),
+                None => return None,
+            }
+        }
+    }
+}
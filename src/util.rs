@@ -1,3 +1,4 @@
+extern crate csv;
 extern crate pyo3;
 use pyo3::exceptions as exc;
 use pyo3::prelude::*;
@@ -10,6 +11,35 @@ pub fn get_optional_single_byte(bytes: Option<&PyBytes>, default: u8) -> PyResul
     }
 }
 
+/// Like [get_optional_single_byte], but returns `None` instead of a default.
+pub fn get_single_byte_option(bytes: Option<&PyBytes>) -> PyResult<Option<u8>> {
+    match bytes {
+        Some(b) => Ok(Some(get_single_byte(b)?)),
+        None => Ok(None),
+    }
+}
+
+/// Extracts a [csv::Terminator] from either a single byte, or `b"\r\n"`
+/// for [csv::Terminator::CRLF].
+///
+/// When reading, [csv::Terminator::CRLF] also ends records at a lone `\r`
+/// or `\n`.
+pub fn get_terminator(bytes: &PyBytes) -> PyResult<csv::Terminator> {
+    if bytes.as_bytes() == b"\r\n" {
+        Ok(csv::Terminator::CRLF)
+    } else {
+        Ok(csv::Terminator::Any(get_single_byte(bytes)?))
+    }
+}
+
+/// The bytes of a [csv::Terminator], as accepted by [get_terminator].
+pub fn terminator_bytes(terminator: csv::Terminator) -> Vec<u8> {
+    match terminator {
+        csv::Terminator::Any(b) => vec![b],
+        _ => b"\r\n".to_vec(),
+    }
+}
+
 /// Extracts a single u8 from a PyBytes object
 /// If the PyBytes object contains more or less than 1 byte, an error is returned.
 pub fn get_single_byte(bytes: &PyBytes) -> PyResult<u8> {
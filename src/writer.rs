@@ -1,36 +1,215 @@
 extern crate csv;
 extern crate pyo3;
 
-use pyo3::prelude::{pyclass, pymethods};
+use pyo3::prelude::{pyclass, pymethods, pyproto};
 
+use compression::{Compression, Encoder};
+use dialect::Dialect;
 use py_file::PyFile;
+use pyo3::class::PyContextProtocol;
 use pyo3::exceptions as exc;
 use pyo3::types::PyBytes;
+use pyo3::types::PyDict;
 use pyo3::types::PyObjectRef;
+use pyo3::types::PyString;
 use pyo3::types::PyTuple;
+use pyo3::types::PyType;
 use pyo3::FromPyObject;
 use pyo3::ObjectProtocol;
+use pyo3::PyObject;
 use pyo3::PyRawObject;
 use pyo3::PyResult;
 use pyo3::PyTryFrom;
 use pyo3::Python;
-use util::get_optional_single_byte;
+use reader::{make_error_position, UnequalLengthsError};
+use std::io;
+use std::io::Write;
 
 #[pyclass(subclass)]
 pub struct CSVWriter {
-    writer: csv::Writer<PyFile>,
+    writer: csv::Writer<PositionWriter<Encoder<PyFile>>>,
+    /// Whether records of unequal lengths are allowed.
+    flexible: bool,
+    /// Length of the first record written, used to check the length of the
+    /// following records if not [CSVWriter::flexible].
+    expected_len: Option<usize>,
+    /// Number of records written.
+    records: u64,
+    /// Codec error handler used when encoding fields.
+    errors: String,
+    /// Whether the writer has been closed.
+    closed: bool,
 }
 
-fn parse_quote_style(quote_style: &str) -> PyResult<csv::QuoteStyle> {
-    match quote_style {
-        "necessary" => Ok(csv::QuoteStyle::Necessary),
-        "always" => Ok(csv::QuoteStyle::Always),
-        "never" => Ok(csv::QuoteStyle::Never),
-        "non_numeric" => Ok(csv::QuoteStyle::NonNumeric),
-        _ => Err(exc::ValueError::py_err(format!(
-            "Invalid quote style: {:?}",
-            quote_style
-        ))),
+/// Wraps a [Write], keeping track of the byte offset and line number of the
+/// data written to it.
+pub struct PositionWriter<W: Write> {
+    inner: W,
+    position: csv::Position,
+    /// The byte ending each line, the last byte of the record terminator.
+    line_end: u8,
+}
+
+impl<W: Write> PositionWriter<W> {
+    /// Wraps `inner`, counting the lines ended by `terminator`.
+    pub fn new(inner: W, terminator: csv::Terminator) -> PositionWriter<W> {
+        PositionWriter {
+            inner,
+            position: csv::Position::new(),
+            line_end: match terminator {
+                csv::Terminator::Any(b) => b,
+                _ => b'\n',
+            },
+        }
+    }
+
+    /// Position after the last byte written.
+    ///
+    /// Note: [csv::Writer] buffers its output, flush it before reading the
+    /// position.
+    pub fn position(&self) -> &csv::Position {
+        &self.position
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+}
+
+impl<W: Write> Write for PositionWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let lines = buf[..written]
+            .iter()
+            .filter(|&&b| b == self.line_end)
+            .count();
+        let byte = self.position.byte() + written as u64;
+        let line = self.position.line() + lines as u64;
+        self.position.set_byte(byte).set_line(line);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Builds a [`csv::Writer`] from the Python arguments shared by [CSVWriter]
+/// and [CSVDictWriter]. The CSV options override the options of `dialect`,
+/// see [Dialect::from_object], and the Python file `fd` is wrapped in an
+/// [Encoder] for `compression`.
+fn make_writer(
+    py: Python,
+    fd: &PyObjectRef,
+    compression: Option<String>,
+    compression_level: Option<u32>,
+    dialect: Option<&PyObjectRef>,
+    delimiter: Option<&PyBytes>,
+    terminator: Option<&PyBytes>,
+    quotechar: Option<&PyBytes>,
+    escape: Option<&PyBytes>,
+    double_quote: Option<bool>,
+    quote_style: Option<String>,
+    comment: Option<&PyBytes>,
+) -> PyResult<csv::Writer<PositionWriter<Encoder<PyFile>>>> {
+    let mut dialect = Dialect::from_object(py, dialect)?;
+    dialect.update(
+        delimiter,
+        terminator,
+        quotechar,
+        escape,
+        double_quote,
+        quote_style,
+        comment,
+    )?;
+    let compression = Compression::from_name(compression.unwrap_or("none".into()).as_str())?;
+    let encoder = Encoder::new(PyFile::extract(fd)?, compression, compression_level)?;
+    Ok(dialect
+        .writer_builder()?
+        // Record lengths are checked by CSVWriter, before anything is
+        // written.
+        .flexible(true)
+        .from_writer(PositionWriter::new(encoder, dialect.terminator)))
+}
+
+/// Flushes `writer`, then finishes the compressed stream.
+fn close_writer(writer: &mut csv::Writer<PositionWriter<Encoder<PyFile>>>) -> PyResult<()> {
+    writer.flush()?;
+    Ok(writer.get_mut().get_mut().finish()?)
+}
+
+/// Raises a `ValueError` if the writer is `closed`, like writing to a closed
+/// Python file does.
+fn check_open(closed: bool) -> PyResult<()> {
+    if closed {
+        return Err(exc::ValueError::py_err("I/O operation on closed writer"));
+    }
+    Ok(())
+}
+
+/// Encodes a Python `str` as UTF-8.
+///
+/// Strings that can't be encoded as-is, i.e. that contain lone surrogates,
+/// are encoded using the `errors` [codec error handler]. This allows writing
+/// back fields read by `CSVReader` with `errors="surrogateescape"`.
+///
+///  [codec error handler]: https://docs.python.org/3/library/codecs.html#error-handlers
+fn encode_str(value: &PyObjectRef, errors: &str) -> PyResult<Vec<u8>> {
+    match value.extract::<String>() {
+        Ok(s) => Ok(s.into_bytes()),
+        Err(error) => {
+            if !value.py().is_instance::<PyString, PyObjectRef>(value)? {
+                return Err(error);
+            }
+            let encoded = value.call_method1("encode", ("utf-8", errors))?;
+            let encoded_bytes: &PyBytes = <PyBytes as PyTryFrom>::try_from(encoded)?;
+            Ok(encoded_bytes.as_bytes().to_vec())
+        }
+    }
+}
+
+/// Checks that the `errors` codec error handler exists, raising a
+/// `LookupError` otherwise, and returns it. Defaults to `"strict"`.
+fn parse_errors(py: Python, errors: Option<String>) -> PyResult<String> {
+    let errors = errors.unwrap_or("strict".into());
+    py.import("codecs")?
+        .call1("lookup_error", (errors.as_str(),))?;
+    Ok(errors)
+}
+
+/// Writes a record with `writer`, converting any [csv::Error] to a [PyErr].
+fn write_record<W, I, T>(writer: &mut csv::Writer<W>, record: I) -> PyResult<()>
+where
+    W: Write,
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    match writer.write_record(record) {
+        Ok(r) => Ok(r),
+        Err(error) => {
+            error!("Could not write record: {:?}", error);
+            Err(exc::IOError::py_err(format!(
+                "Could not write record: {:?}",
+                error
+            )))
+        }
+    }
+}
+
+impl CSVWriter {
+    /// Creates a `rustcsv.error.UnequalLengthsError` for the next record.
+    fn unequal_lengths_error(&mut self, expected_len: usize, len: usize) -> PyResult<PyErr> {
+        // Flush the previous records so that the position is up to date.
+        self.writer.flush()?;
+        let mut position = self.writer.get_ref().position().clone();
+        position.set_record(self.records);
+        Ok(UnequalLengthsError::py_err((
+            format!(
+                "Unequal lengths: Expected length {:?} got length {:?}",
+                expected_len, len,
+            ),
+            make_error_position(position)?,
+        )))
     }
 }
 
@@ -44,22 +223,44 @@ impl CSVWriter {
         escape: Option<&PyBytes>,
         double_quote: Option<bool>,
         quote_style: Option<String>,
+        delimiter: Option<&PyBytes>,
+        quotechar: Option<&PyBytes>,
+        comment: Option<&PyBytes>,
+        flexible: Option<bool>,
+        errors: Option<String>,
+        compression: Option<String>,
+        compression_level: Option<u32>,
+        dialect: Option<&PyObjectRef>,
+        py: Python,
     ) -> PyResult<()> {
-        let writer = csv::WriterBuilder::new()
-            .double_quote(double_quote.unwrap_or(true))
-            .terminator(csv::Terminator::Any(get_optional_single_byte(
-                terminator, b'\n',
-            )?))
-            .escape(get_optional_single_byte(escape, b'\\')?)
-            .quote_style(parse_quote_style(
-                quote_style.unwrap_or("necessary".into()).as_str(),
-            )?)
-            .from_writer(PyFile::extract(fd)?);
-        obj.init(|| CSVWriter { writer })
+        let writer = make_writer(
+            py,
+            fd,
+            compression,
+            compression_level,
+            dialect,
+            delimiter,
+            terminator,
+            quotechar,
+            escape,
+            double_quote,
+            quote_style,
+            comment,
+        )?;
+        let errors = parse_errors(py, errors)?;
+        obj.init(|| CSVWriter {
+            writer,
+            flexible: flexible.unwrap_or(false),
+            expected_len: None,
+            records: 0,
+            errors,
+            closed: false,
+        })
     }
 
     /// Writes a CSV row to the file.
     fn writerow(&mut self, record: &PyObjectRef, py: Python) -> PyResult<()> {
+        check_open(self.closed)?;
         if !py.is_instance::<PyTuple, PyObjectRef>(record)? {
             return Err(exc::TypeError::py_err(format!(
                 "Expected tuple, got {:?}",
@@ -68,27 +269,241 @@ impl CSVWriter {
         }
         debug!("record: {:?}", record);
         let record_tuple: &PyTuple = <PyTuple as PyTryFrom>::try_from(record)?;
-        let r = record_tuple.iter().map(|i| {
-            // TODO: Better error handling when item is not a string
-            i.extract::<String>().unwrap_or_else(|err| {
-                error!("Could not convert {:?} to string: {:?}", i, err);
-                "invalid".into()
-            })
-        });
-        match self.writer.write_record(r) {
-            Ok(r) => Ok(r),
-            Err(error) => {
-                error!("Could not write record: {:?}", error);
-                Err(exc::IOError::py_err(format!(
-                    "Could not write record: {:?}",
-                    error
-                )))
+        let errors = self.errors.as_str();
+        let r: Vec<Vec<u8>> = record_tuple
+            .iter()
+            .map(|i| encode_str(i, errors))
+            .collect::<PyResult<_>>()?;
+        if !self.flexible {
+            match self.expected_len {
+                Some(expected_len) if expected_len != r.len() => {
+                    return Err(self.unequal_lengths_error(expected_len, r.len())?);
+                }
+                Some(_) => {}
+                None => self.expected_len = Some(r.len()),
             }
         }
+        write_record(&mut self.writer, r)?;
+        self.records += 1;
+        Ok(())
     }
 
-    /// Flush the underlying [PyFile] to disk.
+    /// Flush the underlying [PyFile] to disk, including the data compressed
+    /// so far, without finishing the compressed stream.
     fn flush(&mut self) -> PyResult<()> {
+        check_open(self.closed)?;
         Ok(self.writer.flush()?)
     }
+
+    /// Flushes the underlying [PyFile] and finishes the compressed stream,
+    /// if `compression` is set. Writing or flushing after closing raises a
+    /// `ValueError`, closing again does nothing. The file itself is not
+    /// closed.
+    fn close(&mut self) -> PyResult<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        close_writer(&mut self.writer)
+    }
+}
+
+#[pyproto]
+impl<'p> PyContextProtocol<'p> for CSVWriter {
+    fn __enter__(&mut self) -> PyResult<PyObject> {
+        Ok(self.into())
+    }
+
+    /// Closes the writer, see [CSVWriter::close].
+    fn __exit__(
+        &mut self,
+        _ty: Option<&'p PyType>,
+        _value: Option<&'p PyObjectRef>,
+        _traceback: Option<&'p PyObjectRef>,
+    ) -> PyResult<bool> {
+        self.close()?;
+        Ok(false)
+    }
+}
+
+/// What [CSVDictWriter] does when a row contains keys not in `fieldnames`.
+enum ExtrasAction {
+    /// Raise a `ValueError`.
+    Raise,
+    /// Silently drop the extra keys.
+    Ignore,
+}
+
+fn parse_extras_action(extras_action: &str) -> PyResult<ExtrasAction> {
+    match extras_action {
+        "raise" => Ok(ExtrasAction::Raise),
+        "ignore" => Ok(ExtrasAction::Ignore),
+        _ => Err(exc::ValueError::py_err(format!(
+            "Invalid extras action: {:?}",
+            extras_action
+        ))),
+    }
+}
+
+/// Writes `dict` rows, ordering the values by `fieldnames`.
+///
+/// The `dict`-based counterpart of [CSVWriter], like `csv.DictWriter` is to
+/// `csv.writer`.
+#[pyclass(subclass)]
+pub struct CSVDictWriter {
+    writer: csv::Writer<PositionWriter<Encoder<PyFile>>>,
+    /// Column names, determines the order of the values in each row.
+    fieldnames: Vec<String>,
+    /// Value written for keys in `fieldnames` that are missing from a row.
+    restval: String,
+    extras_action: ExtrasAction,
+    /// Codec error handler used when encoding fields.
+    errors: String,
+    /// Whether the writer has been closed.
+    closed: bool,
+}
+
+#[pymethods]
+impl CSVDictWriter {
+    #[new]
+    fn __new__(
+        obj: &PyRawObject,
+        fd: &'static PyObjectRef,
+        fieldnames: Vec<String>,
+        restval: Option<String>,
+        extrasaction: Option<String>,
+        terminator: Option<&PyBytes>,
+        escape: Option<&PyBytes>,
+        double_quote: Option<bool>,
+        quote_style: Option<String>,
+        delimiter: Option<&PyBytes>,
+        quotechar: Option<&PyBytes>,
+        comment: Option<&PyBytes>,
+        errors: Option<String>,
+        compression: Option<String>,
+        compression_level: Option<u32>,
+        dialect: Option<&PyObjectRef>,
+        py: Python,
+    ) -> PyResult<()> {
+        let extras_action = parse_extras_action(extrasaction.unwrap_or("raise".into()).as_str())?;
+        let writer = make_writer(
+            py,
+            fd,
+            compression,
+            compression_level,
+            dialect,
+            delimiter,
+            terminator,
+            quotechar,
+            escape,
+            double_quote,
+            quote_style,
+            comment,
+        )?;
+        let errors = parse_errors(py, errors)?;
+        obj.init(|| CSVDictWriter {
+            writer,
+            fieldnames,
+            restval: restval.unwrap_or_default(),
+            extras_action,
+            errors,
+            closed: false,
+        })
+    }
+
+    /// Writes a row containing the `fieldnames` to the file.
+    fn writeheader(&mut self) -> PyResult<()> {
+        check_open(self.closed)?;
+        write_record(&mut self.writer, &self.fieldnames)
+    }
+
+    /// Writes a CSV row from a `dict` to the file.
+    fn writerow(&mut self, record: &PyObjectRef, py: Python) -> PyResult<()> {
+        check_open(self.closed)?;
+        if !py.is_instance::<PyDict, PyObjectRef>(record)? {
+            return Err(exc::TypeError::py_err(format!(
+                "Expected dict, got {:?}",
+                record
+            )));
+        }
+        debug!("record: {:?}", record);
+        let record_dict: &PyDict = <PyDict as PyTryFrom>::try_from(record)?;
+
+        if let ExtrasAction::Raise = self.extras_action {
+            let extras: Vec<&PyObjectRef> = record_dict
+                .iter()
+                .map(|(key, _)| key)
+                .filter(|key| match key.extract::<String>() {
+                    Ok(k) => !self.fieldnames.contains(&k),
+                    Err(_) => true,
+                })
+                .collect();
+            if !extras.is_empty() {
+                return Err(exc::ValueError::py_err(format!(
+                    "dict contains fields not in fieldnames: {:?}",
+                    extras
+                )));
+            }
+        }
+
+        let mut r: Vec<Vec<u8>> = Vec::with_capacity(self.fieldnames.len());
+        for key in self.fieldnames.iter() {
+            match record_dict.get_item(key) {
+                Some(value) => {
+                    if !py.is_instance::<PyString, PyObjectRef>(value)? {
+                        return Err(exc::TypeError::py_err(format!(
+                            "Expected str value for {:?}, got {:?}",
+                            key, value
+                        )));
+                    }
+                    r.push(encode_str(value, self.errors.as_str())?)
+                }
+                None => r.push(self.restval.clone().into_bytes()),
+            }
+        }
+        write_record(&mut self.writer, r)
+    }
+
+    /// Flush the underlying [PyFile] to disk, including the data compressed
+    /// so far, without finishing the compressed stream.
+    fn flush(&mut self) -> PyResult<()> {
+        check_open(self.closed)?;
+        Ok(self.writer.flush()?)
+    }
+
+    /// Flushes the underlying [PyFile] and finishes the compressed stream,
+    /// if `compression` is set. Writing or flushing after closing raises a
+    /// `ValueError`, closing again does nothing. The file itself is not
+    /// closed.
+    fn close(&mut self) -> PyResult<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        close_writer(&mut self.writer)
+    }
+
+    /// The column names.
+    #[getter]
+    fn fieldnames(&self) -> PyResult<Vec<String>> {
+        Ok(self.fieldnames.clone())
+    }
+}
+
+#[pyproto]
+impl<'p> PyContextProtocol<'p> for CSVDictWriter {
+    fn __enter__(&mut self) -> PyResult<PyObject> {
+        Ok(self.into())
+    }
+
+    /// Closes the writer, see [CSVDictWriter::close].
+    fn __exit__(
+        &mut self,
+        _ty: Option<&'p PyType>,
+        _value: Option<&'p PyObjectRef>,
+        _traceback: Option<&'p PyObjectRef>,
+    ) -> PyResult<bool> {
+        self.close()?;
+        Ok(false)
+    }
 }